@@ -1,164 +1,5927 @@
-pub struct App {
-    pub blocks: Vec,
+use crate::store::ChainStore;
+use crate::transaction::{address, sign_message, verify_message, Transaction};
+use chrono::Utc;
+use k256::ecdsa::SigningKey;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+use std::{
+    borrow::{Borrow, Cow},
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt, fs,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// extra check a block has to pass on top of the built-in ones, called with the block and
+// the block before it, see `App::add_validation_rule`
+pub type ValidationRule = Box<dyn Fn(&Block, &Block) -> Result<(), BlockError> + Send + Sync>;
+
+pub struct App<S = Vec<Block>> {
+    // where the chain is kept, in memory unless the app was made `with_store`
+    pub blocks: S,
+    // number of leading zero bits a block hash needs to be accepted, retargeting moves it from
+    // one block to the next, see `next_difficulty`
+    pub difficulty: Difficulty,
+    // lowest difficulty retargeting goes down to when blocks come too slowly, orphans and headers
+    // are only held to this, the blocks before them that decide their difficulty aren't known
+    pub min_difficulty: Difficulty,
+    // hash function used for mining and validating blocks
+    pub hash_algorithm: HashAlgorithm,
+    // network every block has to belong to, part of the hash so blocks can't be replayed elsewhere
+    pub network_id: u32,
+    // how blocks are produced, see `ConsensusMode`
+    pub consensus: ConsensusMode,
+    // stake of every validator, only used under proof of stake
+    pub stakes: BTreeMap<String, u64>,
+    // signed transactions waiting to be mined, oldest first
+    pub mempool: Vec<Transaction>,
+    // most transactions a block may carry, `mine_pending` never puts more into one
+    pub max_txs_per_block: usize,
+    // largest a block may be in its JSON encoding, the format chains are stored in
+    pub max_block_bytes: usize,
+    // blocks whose parent hasn't arrived yet, keyed by the hash of that parent
+    pub orphan_pool: HashMap<String, Vec<Block>>,
+    // most blocks the orphan pool holds, any peer can send blocks with made up parents
+    pub max_orphans: usize,
+    // address credited with the reward of every block this app mines
+    pub miner_address: Option<String>,
+    // summary of the blocks dropped by `prune`, None while the chain still starts at genesis
+    pub pruned_state_root: Option<String>,
+    // last block dropped by `prune`, the parent the first kept block is validated against
+    pub pruned_tip: Option<Block>,
+    // position in `blocks` up to which every block was validated when it was added
+    pub last_validated_height: u64,
+    // balances as of `pruned_tip`, so spends of coins from pruned blocks still validate
+    pub pruned_balances: HashMap<String, i128>,
+    // `Transaction::hash` of every transaction in the pruned blocks, they can't come again either
+    pub pruned_confirmed_txs: HashSet<String>,
+    // retargeting as of `pruned_tip`, the difficulty of the blocks kept follows from the pruned ones
+    pub pruned_retarget: Option<RetargetState>,
+    // how far ahead of the local clock a block timestamp may be, None disables the check
+    pub max_future_drift: Option<Duration>,
+    // largest `data` a block may carry, in bytes of its UTF-8 encoding
+    pub max_data_bytes: usize,
+    // time between blocks that difficulty retargeting aims for
+    pub target_block_time: Duration,
+    // most the work per block may grow or shrink by from one block to the next through
+    // retargeting, rounded down to a power of two
+    pub max_retarget_factor: u32,
+    // known good block hashes by id, a chain disagreeing with any of them is rejected
+    pub checkpoints: BTreeMap<u64, String>,
+    // balance of every address after the tip, updated as blocks are added and rolled back
+    // so queries don't replay the chain, always agrees with what `replay_balances` computes
+    pub balances: HashMap<String, i128>,
+    // most blocks a reorg may roll back off the tip, deeper forks are refused as attacks,
+    // None allows any depth
+    pub max_reorg_depth: Option<usize>,
+    // whether a block may have empty `data`, transactions count as data
+    pub allow_empty_data: bool,
+    // user supplied checks every block after genesis has to pass, in order
+    pub validation_rules: Vec<ValidationRule>,
+    // timestamp every block this app mines gets instead of the local clock, set it to make
+    // mining reproducible, nonces and hashes then only depend on the block contents
+    pub seed_timestamp: Option<i64>,
+    // least time a block has to come after its parent, catches timestamps pulled closer together
+    // than the work could have been done in, zero turns the check off
+    pub min_interval: Duration,
+    // legacy binary string every proof of work hash has to start with, replaces `difficulty`
+    // while set, see `set_difficulty_prefix`
+    pub difficulty_prefix: Option<String>,
+    // `Transaction::hash` of every transaction on the chain, pruned blocks included,
+    // a transaction found in here can't be mined again
+    pub confirmed_txs: HashSet<String>,
+    // timestamp the genesis block has to carry, nodes only end up with the same genesis, and can
+    // sync, when they agree on it
+    // None until a genesis block is created or loaded, which then pins it, `genesis` falls back
+    // to GENESIS_TIMESTAMP and a loaded chain brings its own
+    pub genesis_timestamp: Option<i64>,
+    // one sender per `subscribe` call, dropped once its receiver is gone
+    subscribers: Vec<Sender<ChainEvent>>,
+}
+
+// number of leading zero bits a proof of work hash needs
+// the original DIFFICULTY_PREFIX "00" was matched against the hash written out in binary,
+// so it asked for two zero bits, not two zero hex digits (eight bits) as it reads,
+// the constructors name the unit so the two can't be mixed up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Difficulty(u32);
+
+impl Difficulty {
+    // no proof of work at all, every hash is accepted and nonce 0 is always the first one tried
+    // meant for tests that need many blocks quickly, never for a real network
+    pub const ZERO: Difficulty = Difficulty(0);
+
+    // every bit of the hash zero, no hash but the all zero one meets it
+    pub const MAX: Difficulty = Difficulty(HASH_BITS);
+
+    // more bits than a hash has are capped at MAX, `try_from_bits` refuses them instead
+    pub const fn from_bits(bits: u32) -> Self {
+        if bits > HASH_BITS {
+            Self::MAX
+        } else {
+            Self(bits)
+        }
+    }
+
+    // like `from_bits`, but fails with DifficultyTooHigh for more bits than a hash has,
+    // e.g. for a difficulty that comes from the user
+    pub fn try_from_bits(bits: u32) -> Result<Self, ConfigError> {
+        if bits > HASH_BITS {
+            return Err(ConfigError::DifficultyTooHigh(bits));
+        }
+        Ok(Self(bits))
+    }
+
+    // every hex digit of the hash stands for four bits
+    pub const fn from_hex_zeros(zeros: u32) -> Self {
+        Self::from_bits(zeros.saturating_mul(4))
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    // maximum acceptable hash value, see `target_from_bits`
+    pub fn target(self) -> [u8; 32] {
+        target_from_bits(self.0)
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bits", self.0)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Block {
+// proof of work grinds nonces until the hash meets the difficulty,
+// proof of stake instead picks the validator of each block from the stakes, weighted by stake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsensusMode {
+    #[default]
+    ProofOfWork,
+    ProofOfStake,
+}
+
+// hash functions a chain can be mined with
+// the difficulty check only looks at the digest bytes, so it works the same for all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha3_256,
+    Blake3,
+}
+
+// `data` can be any serializable payload, see `Block::mine_payload`, the chain itself and
+// everything else in this crate uses plain `Block`, which is `Block<String>`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Block<T = String> {
     pub id: u64,
     pub hash: String,
     pub previous_hash: String,
     pub timestamp: i64,
-    pub data: String,
+    pub data: T,
     pub nonce: u64,
+    // coins minted by this block, see `App::block_reward`
+    #[serde(default)]
+    pub reward: u64,
+    // optional list of transactions committed to by `merkle_root`, an alternative to `data`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    // staker that produced the block, only set on proof of stake chains
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<String>,
+    // address credited with the reward, defaults to the validator on proof of stake chains
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub miner: Option<String>,
+    // network the block was produced for, see `App::network_id`
+    #[serde(default, skip_serializing_if = "is_default_network")]
+    pub network_id: u32,
+    // address of whoever signed the block hash with `Block::sign`, unsigned blocks have neither
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub miner_pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    // format of the block, blocks written before versioning was introduced are version 1
+    #[serde(
+        default = "default_version",
+        skip_serializing_if = "is_default_version"
+    )]
+    pub version: u16,
 }
 
-// simplistic basis for mining scheme, when mining a block, data is hashed for block and hash is found
-// which starts with two zeros
-const DIFFICULTY_PREFIX: &str = "00";
+fn default_version() -> u16 {
+    BLOCK_VERSION
+}
 
-// binary representation of a given byte array in form of String
-// used to check whether hash fits the DIFFICULTY_PREFIX condition
-fn hash_to_binary_representation(hash: &[u8]) -> String {
-    let mut res: String = String::default();
-    for c in hash {
-        res.push_str(&format!("{:b}", c));
-    }
-    res
+fn is_default_version(version: &u16) -> bool {
+    *version == BLOCK_VERSION
 }
-impl App {
-    fn new() -> Self {
-        Self { blocks: vec![] }
+
+fn is_default_network(network_id: &u32) -> bool {
+    *network_id == DEFAULT_NETWORK_ID
+}
+
+// most characters of `data` a displayed block shows
+const DISPLAY_DATA_CHARS: usize = 32;
+
+// one line summary for logs and the CLI, `#<id> <hash> <- <previous hash> (nonce=<n>, data="<data>")`
+// with both hashes cut to 8 characters and long data cut off with "..."
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = |hash: &str| hash.chars().take(8).collect::<String>();
+        let mut data: String = self.data.chars().take(DISPLAY_DATA_CHARS).collect();
+        if self.data.chars().nth(DISPLAY_DATA_CHARS).is_some() {
+            data.push_str("...");
+        }
+        write!(
+            f,
+            "#{} {} <- {} (nonce={}, data={:?})",
+            self.id,
+            short(&self.hash),
+            short(&self.previous_hash),
+            self.nonce,
+            data
+        )
     }
+}
 
-    // creates the first, hard-coded, block in blockchain
-    // special block, which is the start of the blockchain
-    fn genesis(&mut self) {
-        let genesis_block = Block {
-            id: 0,
-            timestamp: Utc::now().timestamp(),
-            previous_hash: "genesis".to_string(),
-            data: "genesis".to_string(),
-            nonce: 2836,
-            hash: "0000f816a87f806bb0073dcf026a64fb40c946b5abee2573702828694d5b4c43",
-        };
-        self.blocks.push(genesis_block);
+// field for field copy of `Block` used by the binary format
+// bincode isn't self describing, so the optional fields `Block` leaves out of JSON are always written
+#[derive(Serialize, Deserialize)]
+struct BinaryBlock {
+    id: u64,
+    hash: String,
+    previous_hash: String,
+    timestamp: i64,
+    data: String,
+    nonce: u64,
+    reward: u64,
+    transactions: Vec<Transaction>,
+    merkle_root: Option<String>,
+    validator: Option<String>,
+    miner: Option<String>,
+    network_id: u32,
+    miner_pubkey: Option<String>,
+    signature: Option<String>,
+    version: u16,
+}
+
+impl From<&Block> for BinaryBlock {
+    fn from(block: &Block) -> Self {
+        let block = block.clone();
+        Self {
+            id: block.id,
+            hash: block.hash,
+            previous_hash: block.previous_hash,
+            timestamp: block.timestamp,
+            data: block.data,
+            nonce: block.nonce,
+            reward: block.reward,
+            transactions: block.transactions,
+            merkle_root: block.merkle_root,
+            validator: block.validator,
+            miner: block.miner,
+            network_id: block.network_id,
+            miner_pubkey: block.miner_pubkey,
+            signature: block.signature,
+            version: block.version,
+        }
     }
-    // gets last block in the chain, validate if block is valid and can be added
-    fn try_add_block(&mut self, block: Block) {
-        let latest_block = self.blocks.last().expect("there is atleast a single block");
-        if self.is_block_valid(&block, latest_block) {
-            self.blocks.push(block);
-        } else {
-            error!("invalid block");
+}
+
+impl From<BinaryBlock> for Block {
+    fn from(block: BinaryBlock) -> Self {
+        Self {
+            id: block.id,
+            hash: block.hash,
+            previous_hash: block.previous_hash,
+            timestamp: block.timestamp,
+            data: block.data,
+            nonce: block.nonce,
+            reward: block.reward,
+            transactions: block.transactions,
+            merkle_root: block.merkle_root,
+            validator: block.validator,
+            miner: block.miner,
+            network_id: block.network_id,
+            miner_pubkey: block.miner_pubkey,
+            signature: block.signature,
+            version: block.version,
         }
     }
-    // validating block logic
-    // ensures blockchain adheres to chain property & is hard to tamper with
-    // 
-    fn is_block_valid(&self, block: &Block, previous_block: &Block) -> bool {
-        if block.previous_hash != previous_block.hash {
-            warn!("block id: {}, has an invalid previous hash", block.id);
-            return false;
-        } else if !hash_to_binary_representation(
-            &hex::decode(&block.hash).expect("can't decode from hex");
-        ).starts_with(DIFFICULTY_PREFIX) 
-        {
-            warn!("block id: {}, has an invalid difficulty", block.id);
-            return false;
-        } else if block.id != previous_block.id + 1 {
-            warn!(
-                "block id: {}, is not the block after the latest: {}", block.id, previous_block.id
-            );
-            return false;
-        } else if hex::encode(calculate_hash(
-            block.id,
-            block.timestamp,
-            &block.previous_hash,
-            &block.data,
-            block.nonce,
-        )) != block.hash
-        {
-            warn!("block id: {}, has an invalid hash", block.id);
-            return false;
+}
+
+// everything about a block except its payload, for syncing headers before bodies
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub id: u64,
+    pub previous_hash: String,
+    pub hash: String,
+    pub timestamp: i64,
+    pub nonce: u64,
+}
+
+impl From<&Block> for BlockHeader {
+    fn from(block: &Block) -> Self {
+        Self {
+            id: block.id,
+            previous_hash: block.previous_hash.clone(),
+            hash: block.hash.clone(),
+            timestamp: block.timestamp,
+            nonce: block.nonce,
         }
-        true
     }
-    // validating a whole chain
-    fn is_chain_valid(&mut self, chain: &[Block]) -> bool {
-        for i in 0..chain.len() {
-            if i == 0 {
-                continue;
+}
+
+// summary of the chain returned by `App::get_chain_info`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainInfo {
+    // id of the tip, 0 for an empty chain
+    pub height: u64,
+    pub tip_hash: Option<String>,
+    // difficulty the next block will be mined with
+    pub difficulty: Difficulty,
+}
+
+// immutable view of the chain at the time `App::snapshot` was called
+// cloning it is cheap and it can be handed to other threads, later blocks don't show up in it
+#[derive(Debug, Clone)]
+pub struct ChainSnapshot {
+    blocks: Arc<[Block]>,
+    difficulty: Difficulty,
+}
+
+impl ChainSnapshot {
+    pub fn tip(&self) -> Option<&Block> {
+        self.blocks.last()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id == id)
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    // difficulty the block after the tip was going to be mined with
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+}
+
+// how two chains relate, see `App::diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainDiff {
+    // id of the last block both chains share, None if even their genesis blocks differ
+    pub common_ancestor: Option<u64>,
+    pub only_in_self: Vec<Block>,
+    pub only_in_other: Vec<Block>,
+}
+
+// changes to the chain sent to everyone who called `App::subscribe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    BlockAdded(Box<Block>),
+    Reorg(ReorgReport),
+    // a block passed to `App::try_add_block` was not added, orphans included
+    BlockRejected(BlockError),
+}
+
+// what `App::reorg_to` changed on the local chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorgReport {
+    // id of the last block both chains share, None if they diverge at genesis
+    pub common_ancestor: Option<u64>,
+    pub rolled_back: usize,
+    pub added: usize,
+}
+
+// reasons a block can be rejected when it is added to the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    PreviousHashMismatch,
+    InvalidDifficulty,
+    NonSequentialId,
+    HashMismatch,
+    MerkleRootMismatch,
+    InvalidReward,
+    InvalidTransaction,
+    ReplayedTransaction,
+    InsufficientFunds,
+    InvalidSignature,
+    InvalidValidator,
+    MalformedFields,
+    Orphan,
+    OrphanPoolFull,
+    InvalidHashEncoding,
+    WrongNetwork,
+    DataTooLarge,
+    TooManyTransactions,
+    BlockTooLarge,
+    TimestampBeforeParent,
+    TimestampInFuture,
+    MissingGenesis,
+    UnsupportedVersion,
+    CheckpointMismatch,
+    EmptyData,
+    RuleViolation,
+    TooSoonAfterParent,
+    GenesisTimestampMismatch,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            BlockError::PreviousHashMismatch => "previous hash does not match the latest block",
+            BlockError::InvalidDifficulty => "hash does not meet the difficulty",
+            BlockError::NonSequentialId => "id is not the one after the latest block",
+            BlockError::HashMismatch => "nonce and contents do not produce the block hash",
+            BlockError::MerkleRootMismatch => "merkle root does not match the transactions",
+            BlockError::InvalidReward => "reward does not match the block height",
+            BlockError::InvalidTransaction => "a transaction has an invalid signature",
+            BlockError::ReplayedTransaction => "a transaction is already on the chain",
+            BlockError::InsufficientFunds => "a transaction spends more than its sender has",
+            BlockError::InvalidSignature => "block signature does not match its public key",
+            BlockError::InvalidValidator => "validator was not selected to produce this block",
+            BlockError::MalformedFields => "block fields are out of range or empty",
+            BlockError::Orphan => "parent is not known yet, block kept as an orphan",
+            BlockError::OrphanPoolFull => "parent is not known and the orphan pool is full",
+            BlockError::InvalidHashEncoding => "hash is not valid hex",
+            BlockError::WrongNetwork => "block belongs to a different network",
+            BlockError::DataTooLarge => "block data exceeds the size limit",
+            BlockError::TooManyTransactions => "block carries more transactions than allowed",
+            BlockError::BlockTooLarge => "block exceeds the size limit",
+            BlockError::TimestampBeforeParent => "timestamp is earlier than the previous block",
+            BlockError::TimestampInFuture => "timestamp is too far in the future",
+            BlockError::MissingGenesis => "chain has no genesis block",
+            BlockError::UnsupportedVersion => "block version is not supported",
+            BlockError::CheckpointMismatch => "block hash does not match the checkpoint",
+            BlockError::EmptyData => "block carries no data",
+            BlockError::RuleViolation => "block breaks a custom validation rule",
+            BlockError::TooSoonAfterParent => "block came too soon after its previous block",
+            BlockError::GenesisTimestampMismatch => {
+                "genesis timestamp does not match the configured one"
             }
-            let first = chain.get(i - 1).expect("has to exist");
-            let second = chain.get(i).expect("has to exist");
-            if !self.block.is_block_valid(second, first) {
-                return false;
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+// reasons a whole chain can be rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    BothInvalid,
+    InvalidChain,
+    GenesisExists,
+    ReorgTooDeep,
+    DifferentGenesis,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            ChainError::BothInvalid => "local & remote chains invalid",
+            ChainError::InvalidChain => "chain failed validation",
+            ChainError::GenesisExists => "chain already has a genesis block",
+            ChainError::ReorgTooDeep => "switching would roll back more blocks than allowed",
+            ChainError::DifferentGenesis => "chain starts from a different genesis block",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+// reasons a single transaction is invalid, see `App::first_invalid_transaction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxError {
+    InvalidSignature,
+    // what the transaction needs, amount and fee, and what its sender had before it
+    InsufficientFunds { cost: i128, balance: i128 },
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::InvalidSignature => write!(f, "signature does not match the sender"),
+            TxError::InsufficientFunds { cost, balance } => {
+                write!(f, "spends {} but the sender only has {}", cost, balance)
             }
         }
-        true
     }
+}
 
-    // chooses which chain to use
-    fn choose_chain(&mut self, local: Vec, remote: Vec) -> Vec {
-        // always choose the longest valid chain
-        let is_local_valid = self.is_chain_valid(&local);
-        let is_remote_valid = self.is_chain_valid(&remote);
+impl std::error::Error for TxError {}
 
-        if is_local_valid && is_remote_valid {
-            if local.len() >= remote.len() {
-                local
-            } else {
-                remote
+// reasons a setting of `App` can be refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    EmptyDifficultyPrefix,
+    // the character that is neither 0 nor 1
+    InvalidDifficultyPrefix(char),
+    // length of a prefix longer than the 256 bits a hash has, no hash could ever match it
+    DifficultyPrefixTooLong(usize),
+    // bits asked for by `Difficulty::try_from_bits`, more than a hash has
+    DifficultyTooHigh(u32),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::EmptyDifficultyPrefix => write!(f, "difficulty prefix is empty"),
+            ConfigError::InvalidDifficultyPrefix(c) => {
+                write!(
+                    f,
+                    "difficulty prefix may only contain 0 and 1, found {:?}",
+                    c
+                )
             }
-        } else if is_remote_valid && !is_local_valid {
-            remote
-        } else if !is_remote_valid && is_local_valid {
-            local
-        } else {
-            panic!("local & remote chains invalid");
+            ConfigError::DifficultyPrefixTooLong(len) => write!(
+                f,
+                "difficulty prefix is {} characters long, a hash only has {} bits",
+                len, HASH_BITS
+            ),
+            ConfigError::DifficultyTooHigh(bits) => write!(
+                f,
+                "difficulty of {} bits is more than the {} bits a hash has",
+                bits, HASH_BITS
+            ),
         }
     }
 }
 
-// implementation of mining scheme
-// when new block created, mine_block is called, which returns nonce & a hash
+impl std::error::Error for ConfigError {}
 
-impl Block {
-    pub fn new(id: u64, previous_hash: String, data: String) -> Self {
-        let now = Utc::now();
-        let (nonce, hash) = mine_block(id, now.timestamp(), &previous_hash, &data);
+// error returned by the public API of the crate, wraps the more specific errors above
+#[derive(Debug, thiserror::Error)]
+pub enum BlockchainError {
+    #[error("invalid block: {0}")]
+    Block(#[from] BlockError),
+    #[error("{0}")]
+    Chain(#[from] ChainError),
+    #[error("invalid setting: {0}")]
+    Config(#[from] ConfigError),
+    // a chain was rejected because of the block at `index`, e.g. while loading it
+    #[error("chain is invalid at block {index}: {source}")]
+    InvalidChainAt { index: usize, source: BlockError },
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("malformed binary chain: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("not a chain archive, the magic bytes don't match")]
+    NotAnArchive,
+    #[error("archive format version {0} is not supported")]
+    UnsupportedArchiveVersion(u16),
+}
+
+impl From<(usize, BlockError)> for BlockchainError {
+    fn from((index, source): (usize, BlockError)) -> Self {
+        BlockchainError::InvalidChainAt { index, source }
+    }
+}
+
+// simplistic basis for mining scheme, when mining a block, data is hashed for block and hash is found
+// which starts with two zero bits
+const DEFAULT_DIFFICULTY: Difficulty = Difficulty::from_bits(2);
+
+// difficulty retargeting looks at the timestamps of this many recent blocks
+// and tries to keep the average time between blocks close to the target, see `App::target_block_time`
+const RETARGET_WINDOW: usize = 10;
+const DEFAULT_TARGET_BLOCK_TIME: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_RETARGET_FACTOR: u32 = 4;
+
+// where difficulty retargeting stands after a block: the difficulty that block had to meet and
+// the timestamps of up to RETARGET_WINDOW blocks up to it, oldest first and genesis left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetargetState {
+    pub difficulty: Difficulty,
+    pub window: VecDeque<i64>,
+}
+
+// every block mints a reward which starts at this value and halves every interval of blocks
+const BASE_REWARD: u64 = 50;
+const HALVING_INTERVAL: u64 = 100;
+
+// default number of mempool transactions mined into a single block
+const DEFAULT_MAX_TXS_PER_BLOCK: usize = 10;
+const DEFAULT_MAX_BLOCK_BYTES: usize = 1024 * 1024;
+
+// default number of blocks kept in the orphan pool, over all missing parents
+const DEFAULT_MAX_ORPHANS: usize = 64;
+
+// block format this version of the code produces and accepts, left out of the hash like the
+// network id, so blocks from before versioning keep their hashes
+const BLOCK_VERSION: u16 = 1;
+
+// network of blocks that don't name one, it is left out of the hash so older chains stay valid
+const DEFAULT_NETWORK_ID: u32 = 0;
+
+// blocks more than this far ahead of the local clock are rejected by default
+const DEFAULT_MAX_FUTURE_DRIFT: Duration = Duration::from_secs(2 * 60 * 60);
+
+// default limit on the size of a block's data, so a single block can't bloat the chain
+const DEFAULT_MAX_DATA_BYTES: usize = 64 * 1024;
+
+// first bytes of every file written by `App::export_archive`
+const ARCHIVE_MAGIC: &[u8; 4] = b"RBCA";
+// bumped whenever the archive layout changes
+const ARCHIVE_VERSION: u16 = 1;
+
+// fixed inputs of the genesis block, a real timestamp would make it differ between runs
+// the timestamp is only the default for `App::genesis_timestamp`
+pub const GENESIS_TIMESTAMP: i64 = 1_631_000_000;
+const GENESIS_NONCE: u64 = 2836;
+
+// binary representation of a given byte array in form of String
+// used to show mined hashes in the log and for the legacy prefix,
+// the difficulty check itself works on the bytes
+// every byte is padded to eight bits, otherwise leading zeros of a byte would be dropped
+fn hash_to_binary_representation(hash: &[u8]) -> String {
+    let mut res: String = String::default();
+    for c in hash {
+        res.push_str(&format!("{:08b}", c));
+    }
+    res
+}
+
+// whether the hash written out in binary starts with `prefix`, the original difficulty check
+fn meets_prefix(hash: &[u8], prefix: &str) -> bool {
+    hash_to_binary_representation(hash).starts_with(prefix)
+}
+
+// the fields of a block its hash commits to, everything except the nonce
+#[derive(Debug, Clone)]
+struct BlockContents<'a> {
+    id: u64,
+    timestamp: i64,
+    previous_hash: &'a str,
+    // the payload already encoded as JSON, for string data that is the quoted string
+    data_json: String,
+    merkle_root: Option<&'a str>,
+    validator: Option<&'a str>,
+    miner: Option<&'a str>,
+    network_id: u32,
+    version: u16,
+}
 
+impl<'a> BlockContents<'a> {
+    // contents of a plain data block, without transactions or a validator
+    fn new<T: Serialize + ?Sized>(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &'a str,
+        data: &T,
+    ) -> Self {
         Self {
             id,
-            hash,
-            timestamp: now.timestamp(),
+            timestamp,
             previous_hash,
-            data,
-            nonce,
+            data_json: serde_json::to_string(data).expect("can serialize data"),
+            merkle_root: None,
+            validator: None,
+            miner: None,
+            network_id: DEFAULT_NETWORK_ID,
+            version: BLOCK_VERSION,
         }
     }
+}
 
-    fn mine_block(id: u64, timestamp: i64, previous_hash: &str, data: &str) -> (u64, String) {
-        info!("Mining block...");
-        let mut nonce = 0;
+// the hashed form of a block is its contents as compact JSON with the keys sorted, e.g.
+// {"data":"..","id":1,"nonce":42,"previous_hash":"..","timestamp":..}
+// split around the nonce, so everything up to it can go through the hasher once per block
+// the merkle root, validator, miner, network and version are only present for blocks that have them,
+// so hashes of plain data blocks stay the same as before
+struct HashInput {
+    // everything before the nonce digits, ending in `"nonce":`
+    prefix: String,
+    // everything after them, the closing brace included
+    suffix: String,
+}
 
-        loop {
-            if nonce & 100000 == 0 {
-                info!("nonce: {}", nonce);
+impl HashInput {
+    fn new(contents: &BlockContents) -> Self {
+        fn field(out: &mut String, key: &str, value: impl Serialize) {
+            out.push_str(&serde_json::to_string(key).expect("can serialize key"));
+            out.push(':');
+            out.push_str(&serde_json::to_string(&value).expect("can serialize value"));
+            out.push(',');
+        }
+
+        let mut prefix = String::from("{\"data\":");
+        prefix.push_str(&contents.data_json);
+        prefix.push(',');
+        field(&mut prefix, "id", contents.id);
+        if let Some(merkle_root) = contents.merkle_root {
+            field(&mut prefix, "merkle_root", merkle_root);
+        }
+        if let Some(miner) = contents.miner {
+            field(&mut prefix, "miner", miner);
+        }
+        if contents.network_id != DEFAULT_NETWORK_ID {
+            field(&mut prefix, "network_id", contents.network_id);
+        }
+        prefix.push_str("\"nonce\":");
+
+        let mut suffix = String::from(",");
+        field(&mut suffix, "previous_hash", contents.previous_hash);
+        field(&mut suffix, "timestamp", contents.timestamp);
+        if let Some(validator) = contents.validator {
+            field(&mut suffix, "validator", validator);
+        }
+        if contents.version != BLOCK_VERSION {
+            field(&mut suffix, "version", contents.version);
+        }
+        suffix.pop();
+        suffix.push('}');
+
+        Self { prefix, suffix }
+    }
+}
+
+// hash function state after absorbing the prefix of a block, cloned for every nonce tried
+#[derive(Clone)]
+enum PrefixHasher {
+    Sha256(Sha256),
+    Sha3_256(Box<Sha3_256>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl PrefixHasher {
+    fn new(prefix: &str, algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new().chain(prefix.as_bytes())),
+            HashAlgorithm::Sha3_256 => {
+                Self::Sha3_256(Box::new(Sha3_256::new().chain(prefix.as_bytes())))
             }
-            let hash = calculate_hash(id, timestamp, previous_hash, data, nonce);
-            let binary_hash = hash_to_binary_representation(&hash);
-            if binary_hash.starts_with(DIFFICULTY_PREFIX) {
-                info!(
-                    "block mined! nonce: {}, hash: {}, binary hash: {}",
-                    nonce,
-                    hex::encode(&hash),
-                    binary_hash
-                );
-                return (nonce, hex::encode(&hash));
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(prefix.as_bytes());
+                Self::Blake3(Box::new(hasher))
+            }
+        }
+    }
+
+    fn finish(&self, nonce: u64, suffix: &str) -> Vec<u8> {
+        let nonce = nonce.to_string();
+        match self.clone() {
+            Self::Sha256(hasher) => hasher
+                .chain(nonce.as_bytes())
+                .chain(suffix.as_bytes())
+                .finalize()
+                .to_vec(),
+            Self::Sha3_256(hasher) => hasher
+                .chain(nonce.as_bytes())
+                .chain(suffix.as_bytes())
+                .finalize()
+                .to_vec(),
+            Self::Blake3(mut hasher) => {
+                hasher.update(nonce.as_bytes());
+                hasher.update(suffix.as_bytes());
+                hasher.finalize().as_bytes().to_vec()
+            }
+        }
+    }
+}
+
+// hashes the block contents with the given algorithm, the nonce is what miners vary
+fn calculate_hash(contents: &BlockContents, nonce: u64, algorithm: HashAlgorithm) -> Vec<u8> {
+    let input = HashInput::new(contents);
+    let bytes = format!("{}{}{}", input.prefix, nonce, input.suffix);
+    match algorithm {
+        HashAlgorithm::Sha256 => Sha256::digest(bytes.as_bytes()).to_vec(),
+        HashAlgorithm::Sha3_256 => Sha3_256::digest(bytes.as_bytes()).to_vec(),
+        HashAlgorithm::Blake3 => blake3::hash(bytes.as_bytes()).as_bytes().to_vec(),
+    }
+}
+
+// root of a merkle tree over the given transactions, hex encoded
+// leaves are the sha256 of each transaction, every level hashes pairs of nodes
+// and the last node is duplicated when a level has an odd number of them
+pub fn merkle_root(txs: &[String]) -> String {
+    let mut level: Vec<Vec<u8>> = txs
+        .iter()
+        .map(|tx| Sha256::digest(tx.as_bytes()).to_vec())
+        .collect();
+    if level.is_empty() {
+        return hex::encode(Sha256::digest(b""));
+    }
+
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            let last = level.last().expect("level is not empty").clone();
+            level.push(last);
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    hex::encode(&level[0])
+}
+
+fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+// builds the root `merkle_root` would compute one leaf at a time, for a growing transaction list
+// keeps one complete subtree per set bit of the leaf count, like a binary counter,
+// so both `push` and `root` take O(log n) hashes
+#[derive(Debug, Clone, Default)]
+pub struct MerkleAccumulator {
+    // peaks[i] is the root of a complete subtree of 2^i leaves, if there is one
+    peaks: Vec<Option<Vec<u8>>>,
+    len: usize,
+}
+
+impl MerkleAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // adds the next leaf, `tx_hash` is the hex encoded sha256 of the transaction,
+    // e.g. `Transaction::hash`, the same hash `merkle_root` computes for it
+    pub fn push(&mut self, tx_hash: &str) -> Result<(), hex::FromHexError> {
+        let mut carry = hex::decode(tx_hash)?;
+        for peak in self.peaks.iter_mut() {
+            match peak.take() {
+                Some(left) => carry = hash_pair(&left, &carry),
+                None => {
+                    *peak = Some(carry);
+                    self.len += 1;
+                    return Ok(());
+                }
             }
-            nonce += 1;
+        }
+        self.peaks.push(Some(carry));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // root over every leaf pushed so far, hex encoded, equal to `merkle_root` over them
+    // below the top peak a level has an odd number of nodes whenever only one of its peak and
+    // the node carried up from the levels below exists, that one is paired with itself
+    pub fn root(&self) -> String {
+        let top = match self.peaks.iter().rposition(Option::is_some) {
+            Some(top) => top,
+            None => return hex::encode(Sha256::digest(b"")),
+        };
+        let mut carry: Option<Vec<u8>> = None;
+        for peak in &self.peaks[..top] {
+            carry = match (peak, &carry) {
+                (Some(peak), Some(right)) => Some(hash_pair(peak, right)),
+                (Some(node), None) | (None, Some(node)) => Some(hash_pair(node, node)),
+                (None, None) => None,
+            };
+        }
+        let peak = self.peaks[top].as_ref().expect("top peak is set");
+        match carry {
+            Some(right) => hex::encode(hash_pair(peak, &right)),
+            None => hex::encode(peak),
+        }
+    }
+}
+
+// sibling hashes on the path from leaf `index` up to the root of the tree `merkle_root` builds
+// each is hex encoded and prefixed with the side it sits on, "left:" or "right:",
+// None if there is no such leaf
+pub fn merkle_proof(txs: &[String], index: usize) -> Option<Vec<String>> {
+    if index >= txs.len() {
+        return None;
+    }
+    let mut level: Vec<Vec<u8>> = txs
+        .iter()
+        .map(|tx| Sha256::digest(tx.as_bytes()).to_vec())
+        .collect();
+    let mut index = index;
+    let mut proof = vec![];
+
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            let last = level.last().expect("level is not empty").clone();
+            level.push(last);
+        }
+        let sibling = if index.is_multiple_of(2) {
+            format!("right:{}", hex::encode(&level[index + 1]))
+        } else {
+            format!("left:{}", hex::encode(&level[index - 1]))
+        };
+        proof.push(sibling);
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+        index /= 2;
+    }
+    Some(proof)
+}
+
+// checks that `tx` is a leaf of the tree with root `merkle_root`, given a proof from `merkle_proof`
+// only the path is hashed, the other transactions of the block aren't needed
+pub fn verify_inclusion(tx: &Transaction, proof: &[String], merkle_root: &str) -> bool {
+    let leaf = serde_json::to_string(tx).expect("transaction can be serialized");
+    let mut node = Sha256::digest(leaf.as_bytes()).to_vec();
+    for sibling in proof {
+        let (side, hash) = match sibling.split_once(':') {
+            Some(parts) => parts,
+            None => return false,
+        };
+        let hash = match hex::decode(hash) {
+            Ok(hash) => hash,
+            Err(_) => return false,
+        };
+        node = match side {
+            "left" => hash_pair(&hash, &node),
+            "right" => hash_pair(&node, &hash),
+            _ => return false,
+        };
+    }
+    hex::encode(node) == merkle_root
+}
+
+// merkle root a block with these transactions has to carry, None for blocks without any
+// every transaction enters the tree as its JSON encoding
+fn expected_merkle_root(txs: &[Transaction]) -> Option<String> {
+    if txs.is_empty() {
+        return None;
+    }
+    Some(merkle_root(&merkle_leaves(txs)))
+}
+
+fn merkle_leaves(txs: &[Transaction]) -> Vec<String> {
+    txs.iter()
+        .map(|tx| serde_json::to_string(tx).expect("transaction can be serialized"))
+        .collect()
+}
+
+// number of zero bits the hash starts with
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
         }
     }
+    zeros
+}
+
+// credits the block reward to whoever mined or validated the block and applies its transactions
+fn apply_block(balances: &mut HashMap<String, i128>, block: &Block) {
+    adjust_balances(balances, block, 1);
 }
 
+// undoes `apply_block`, for blocks rolled back in a reorg
+fn revert_block(balances: &mut HashMap<String, i128>, block: &Block) {
+    adjust_balances(balances, block, -1);
+}
+
+fn adjust_balances(balances: &mut HashMap<String, i128>, block: &Block, sign: i128) {
+    for (address, amount) in genesis_allocations(block) {
+        *balances.entry(address).or_default() += sign * i128::from(amount);
+    }
+    if let Some(producer) = block.miner.as_ref().or(block.validator.as_ref()) {
+        *balances.entry(producer.clone()).or_default() += sign * i128::from(block.reward);
+    }
+    for tx in &block.transactions {
+        *balances.entry(tx.from.clone()).or_default() -=
+            sign * (i128::from(tx.amount) + i128::from(tx.fee));
+        *balances.entry(tx.to.clone()).or_default() += sign * i128::from(tx.amount);
+    }
+}
+
+// replays `transactions` on top of `balances` and stops at the first one spending
+// more than its sender has at that point, with its index, signatures are not looked at
+fn spend_transactions(
+    transactions: &[Transaction],
+    balances: &HashMap<String, i128>,
+) -> Result<(), (usize, TxError)> {
+    let mut running: HashMap<&str, i128> = HashMap::new();
+    for (index, tx) in transactions.iter().enumerate() {
+        let amount = i128::from(tx.amount);
+        let cost = amount + i128::from(tx.fee);
+        let from = running
+            .entry(&tx.from)
+            .or_insert_with(|| balances.get(&tx.from).copied().unwrap_or(0));
+        if *from < cost {
+            return Err((
+                index,
+                TxError::InsufficientFunds {
+                    cost,
+                    balance: *from,
+                },
+            ));
+        }
+        *from -= cost;
+        *running
+            .entry(&tx.to)
+            .or_insert_with(|| balances.get(&tx.to).copied().unwrap_or(0)) += amount;
+    }
+    Ok(())
+}
+
+// records the hash of every transaction in the block as confirmed
+fn confirm_block(confirmed: &mut HashSet<String>, block: &Block) {
+    confirmed.extend(block.transactions.iter().map(Transaction::hash));
+}
+
+// data of a genesis block created by `App::genesis_with_allocations`
+#[derive(Serialize, Deserialize)]
+struct GenesisData {
+    allocations: BTreeMap<String, u64>,
+}
+
+// starting balances a genesis block hands out, empty for every other block
+// they live in the data, so the genesis hash commits to them
+fn genesis_allocations(block: &Block) -> BTreeMap<String, u64> {
+    if block.id != 0 {
+        return BTreeMap::new();
+    }
+    serde_json::from_str::<GenesisData>(&block.data)
+        .map(|genesis| genesis.allocations)
+        .unwrap_or_default()
+}
+
+// checks that `header` links to `previous` and, when a difficulty is given, meets it
+pub(crate) fn validate_header(
+    header: &BlockHeader,
+    previous: &BlockHeader,
+    difficulty: Option<Difficulty>,
+) -> Result<(), BlockError> {
+    if header.previous_hash != previous.hash {
+        warn!("header id: {}, has an invalid previous hash", header.id);
+        return Err(BlockError::PreviousHashMismatch);
+    }
+    if previous.id.checked_add(1) != Some(header.id) {
+        warn!("header id: {}, is not the next id", header.id);
+        return Err(BlockError::NonSequentialId);
+    }
+    if let Some(difficulty) = difficulty {
+        let hash = match hex::decode(&header.hash) {
+            Ok(hash) => hash,
+            Err(_) => return Err(BlockError::InvalidHashEncoding),
+        };
+        if !meets_target(&hash, &difficulty.target()) {
+            warn!("header id: {}, has an invalid difficulty", header.id);
+            return Err(BlockError::InvalidDifficulty);
+        }
+    }
+    Ok(())
+}
+
+// false only if both chains start at a genesis block and those differ,
+// after pruning there is no genesis left to compare against
+fn same_genesis(a: Option<&Block>, b: Option<&Block>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) if a.id == 0 && b.id == 0 && a.hash != b.hash => {
+            warn!("chains start from different genesis blocks");
+            false
+        }
+        _ => true,
+    }
+}
+
+// quotes a CSV field when it contains a separator, quote or line break, doubling inner quotes
+fn escape_csv(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// number of bits in every hash this crate produces, whichever `HashAlgorithm` is used
+const HASH_BITS: u32 = 256;
+
+// mining progress is logged every LOG_INTERVAL nonces
+const LOG_INTERVAL: u64 = 100_000;
+
+// `App::benchmark_hashrate` looks at the clock every BENCHMARK_CHECK_INTERVAL nonces
+const BENCHMARK_CHECK_INTERVAL: u64 = 1024;
+
+fn should_log(nonce: u64) -> bool {
+    nonce.is_multiple_of(LOG_INTERVAL)
+}
+
+// checks whether the hash starts with at least as many zero bits as the difficulty asks for
+// works on the bytes directly instead of building a binary string first
+pub fn meets_difficulty(hash: &[u8], difficulty: Difficulty) -> bool {
+    leading_zero_bits(hash) >= difficulty.bits()
+}
+
+// largest 256 bit hash with at least `bits` leading zero bits, i.e. the first `bits` bits
+// cleared and every other bit set, a hash meets the difficulty iff it is at most this value
+pub fn target_from_bits(bits: u32) -> [u8; 32] {
+    let mut target = [0xffu8; 32];
+    for (i, byte) in target.iter_mut().enumerate() {
+        let zeros = bits.saturating_sub(i as u32 * 8).min(8);
+        *byte = if zeros == 8 { 0 } else { 0xff >> zeros };
+    }
+    target
+}
+
+// compares the hash against the target as big endian 256 bit numbers
+pub fn meets_target(hash: &[u8], target: &[u8; 32]) -> bool {
+    hash.len() == target.len() && hash <= &target[..]
+}
+
+impl App {
+    // creates an empty app without any blocks, `genesis` still has to be called on it
+    pub fn new() -> Self {
+        Self::with_difficulty(DEFAULT_DIFFICULTY)
+    }
+
+    /// creates a ready to use app whose chain holds just the genesis block
+    ///
+    /// ```
+    /// use rust_blockchain_example::app::App;
+    ///
+    /// let app = App::init();
+    /// assert_eq!(app.blocks.len(), 1);
+    /// ```
+    pub fn init() -> Self {
+        let mut app = Self::new();
+        app.genesis();
+        app
+    }
+
+    // deterministic chain of `n` blocks, genesis included, for reproducible tests
+    // blocks are `target_block_time` apart starting at the genesis timestamp and carry
+    // "block <id>" as data, so the same `n` always gives byte identical chains
+    pub fn test_chain(n: usize) -> Self {
+        let mut app = Self::new();
+        if n == 0 {
+            return app;
+        }
+        app.genesis();
+        let spacing = app.target_block_time.as_secs() as i64;
+        for id in 1..n as u64 {
+            let latest_block = app.blocks.last().expect("there is atleast a single block");
+            let block = Block::builder()
+                .id(id)
+                .previous_hash(latest_block.hash.clone())
+                .data(format!("block {}", id))
+                .timestamp(app.genesis_timestamp.unwrap_or(GENESIS_TIMESTAMP) + id as i64 * spacing)
+                .difficulty(app.difficulty)
+                .hash_algorithm(app.hash_algorithm)
+                .build();
+            app.try_add_block(block)
+                .expect("test chain blocks are valid");
+        }
+        app
+    }
+
+    // creates an empty app which mines and validates blocks with the given difficulty
+    // `Difficulty::ZERO` turns proof of work off, which is only safe in tests
+    pub fn with_difficulty(difficulty: Difficulty) -> Self {
+        Self::with_store(vec![], difficulty)
+    }
+
+    // creates an empty proof of stake app, blocks are produced by the given stakers
+    pub fn with_proof_of_stake(stakes: BTreeMap<String, u64>) -> Self {
+        Self {
+            consensus: ConsensusMode::ProofOfStake,
+            stakes,
+            ..Self::new()
+        }
+    }
+
+    // creates an empty app which mines and validates blocks with the given hash function
+    pub fn with_hash_algorithm(algo: HashAlgorithm) -> Self {
+        Self {
+            hash_algorithm: algo,
+            ..Self::new()
+        }
+    }
+
+    // creates an empty app whose blocks only hash and validate on the given network
+    pub fn with_network_id(network_id: u32) -> Self {
+        Self {
+            network_id,
+            ..Self::new()
+        }
+    }
+
+    // reward of the block at `height`, halved every HALVING_INTERVAL blocks
+    // once it has been halved 64 times nothing is left and it stays at zero
+    pub fn block_reward(height: u64) -> u64 {
+        let halvings = height / HALVING_INTERVAL;
+        if halvings >= u64::from(u64::BITS) {
+            0
+        } else {
+            BASE_REWARD >> halvings
+        }
+    }
+
+    // what the producer of the block at `height` carrying `transactions` is paid,
+    // the block reward plus every fee
+    pub fn expected_reward(height: u64, transactions: &[Transaction]) -> u64 {
+        transactions
+            .iter()
+            .fold(App::block_reward(height), |reward, tx| {
+                reward.saturating_add(tx.fee)
+            })
+    }
+
+    // replays the block's transactions on top of `balances`, the balances before the block,
+    // and rejects it as soon as one of them spends more than its sender has at that point,
+    // so two transactions in the same block can't spend the same coins
+    // the block reward only becomes spendable in the blocks after it
+    pub fn validate_block_transactions(
+        block: &Block,
+        balances: &HashMap<String, i128>,
+    ) -> Result<(), BlockError> {
+        spend_transactions(&block.transactions, balances).map_err(|(index, e)| {
+            warn!(
+                "block id: {}, transaction {} from {}: {}",
+                block.id, index, block.transactions[index].from, e
+            );
+            BlockError::InsufficientFunds
+        })
+    }
+
+    // cumulative work that went into a chain, each block counts 2^(leading zero bits of its hash)
+    // this is how many hashes it takes on average to find such a block
+    pub fn total_work(chain: &[Block]) -> u128 {
+        chain
+            .iter()
+            .map(|block| {
+                1u128
+                    .checked_shl(block.hash_zero_bits())
+                    .unwrap_or(u128::MAX)
+            })
+            .fold(0, u128::saturating_add)
+    }
+
+    // checks only that every id is one more than the one before, nothing about hashes,
+    // to tell id problems apart from other corruption
+    // the first id is taken as is, a pruned chain doesn't start at 0
+    // returns the first id that breaks the sequence, e.g. a duplicate or the one after a gap
+    pub fn verify_id_sequence(chain: &[Block]) -> Result<(), u64> {
+        for pair in chain.windows(2) {
+            if pair[0].id.checked_add(1) != Some(pair[1].id) {
+                return Err(pair[1].id);
+            }
+        }
+        Ok(())
+    }
+
+    // whether `proof`, e.g. from `work_proof`, matches the trusted `expected` one
+    pub fn verify_work_proof(proof: &str, expected: &str) -> bool {
+        proof.eq_ignore_ascii_case(expected)
+    }
+
+    // reads a chain previously written by `save_to_file`
+    // the stored blocks are re-validated, a corrupt chain is an error rather than a panic
+    // settings aren't stored with the chain, this validates with the defaults, see `with_file`
+    pub fn load_from_file(path: &Path) -> Result<App, BlockchainError> {
+        App::new().with_file(path)
+    }
+
+    // builds an app from untrusted blocks, e.g. freshly deserialized ones, validating all of them
+    // the first block has to be a genesis block, an empty list fails with MissingGenesis at index 0
+    pub fn from_blocks(blocks: Vec<Block>) -> Result<App, (usize, BlockError)> {
+        App::new().with_blocks(blocks)
+    }
+
+    // reads an archive written by `export_archive`, the blocks are validated by `from_blocks`
+    // input that doesn't start with ARCHIVE_MAGIC or has another format version is refused
+    // before any block is decoded
+    pub fn import_archive(r: impl Read) -> Result<App, BlockchainError> {
+        App::new().with_archive(r)
+    }
+
+    // decodes a chain written by `to_bytes`, validated by `from_blocks` like `load_from_file`
+    pub fn from_bytes(bytes: &[u8]) -> Result<App, BlockchainError> {
+        App::new().with_bytes(bytes)
+    }
+}
+
+// everything that doesn't need the blocks in a `Vec`, it reaches them one at a time through
+// the `ChainStore`, so it works on any of them
+impl<S: ChainStore> App<S> {
+    // creates an app keeping its chain in `store`, which may already hold blocks,
+    // mining and validating with the given difficulty like `with_difficulty`
+    pub fn with_store(store: S, difficulty: Difficulty) -> Self {
+        if difficulty.is_zero() {
+            warn!("difficulty is zero, blocks need no proof of work, never use this on a real network");
+        }
+        let mut app = Self {
+            blocks: store,
+            difficulty,
+            min_difficulty: Difficulty::ZERO,
+            hash_algorithm: HashAlgorithm::default(),
+            network_id: DEFAULT_NETWORK_ID,
+            consensus: ConsensusMode::default(),
+            stakes: BTreeMap::new(),
+            mempool: vec![],
+            max_txs_per_block: DEFAULT_MAX_TXS_PER_BLOCK,
+            max_block_bytes: DEFAULT_MAX_BLOCK_BYTES,
+            orphan_pool: HashMap::new(),
+            max_orphans: DEFAULT_MAX_ORPHANS,
+            miner_address: None,
+            pruned_state_root: None,
+            pruned_tip: None,
+            pruned_balances: HashMap::new(),
+            pruned_confirmed_txs: HashSet::new(),
+            pruned_retarget: None,
+            last_validated_height: 0,
+            max_future_drift: Some(DEFAULT_MAX_FUTURE_DRIFT),
+            max_data_bytes: DEFAULT_MAX_DATA_BYTES,
+            target_block_time: DEFAULT_TARGET_BLOCK_TIME,
+            max_retarget_factor: DEFAULT_MAX_RETARGET_FACTOR,
+            checkpoints: BTreeMap::new(),
+            balances: HashMap::new(),
+            max_reorg_depth: None,
+            allow_empty_data: true,
+            validation_rules: vec![],
+            seed_timestamp: None,
+            min_interval: Duration::ZERO,
+            difficulty_prefix: None,
+            genesis_timestamp: None,
+            confirmed_txs: HashSet::new(),
+            subscribers: vec![],
+        };
+        app.balances = app.replay_balances();
+        app.confirmed_txs = app.replay_confirmed_txs();
+        app
+    }
+
+    // iterates over the blocks from genesis to the tip
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter_blocks()
+    }
+
+    // query methods for serving the chain, e.g. over JSON-RPC
+    // they return owned, serializable values and never change the chain
+    pub fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.blocks
+            .iter_blocks()
+            .find(|block| block.hash == hash)
+            .cloned()
+    }
+
+    pub fn snapshot(&self) -> ChainSnapshot {
+        ChainSnapshot {
+            blocks: self.blocks.iter_blocks().cloned().collect(),
+            difficulty: self.next_difficulty(),
+        }
+    }
+
+    // validating a whole chain
+    // an empty chain has no block that could be invalid, so it counts as valid,
+    // `from_blocks` is the place that insists on a genesis block
+    // blocks identical to the local ones up to `last_validated_height` were checked when they
+    // were added and are skipped, `find_invalid_block` always checks everything
+    fn is_chain_valid(&mut self, chain: &[Block]) -> bool {
+        let trusted = chain
+            .iter()
+            .zip(self.blocks.iter_blocks())
+            .take(self.last_validated_height.saturating_add(1) as usize)
+            .take_while(|(block, known)| block == known)
+            .count();
+        self.validate_blocks_from(chain, trusted).is_ok()
+    }
+
+    // mean time between the last `window` blocks, None unless the chain has at least that many
+    // and `window` spans at least one interval
+    // the genesis timestamp is fixed, a window reaching back to it includes the gap since then
+    pub fn average_block_time(&self, window: usize) -> Option<Duration> {
+        if window < 2 || window > self.blocks.len() {
+            return None;
+        }
+        let last = self.blocks.tip()?;
+        let first = self.blocks.get_block(last.id + 1 - window as u64)?;
+        let elapsed = u64::try_from(last.timestamp.saturating_sub(first.timestamp)).unwrap_or(0);
+        Some(Duration::from_secs(elapsed) / u32::try_from(window - 1).ok()?)
+    }
+
+    // time from the first block to the tip, None for a chain of less than two blocks
+    // after pruning the first block is the oldest one kept rather than genesis
+    pub fn chain_duration(&self) -> Option<Duration> {
+        if self.blocks.len() < 2 {
+            return None;
+        }
+        let first = self.blocks.get_block(self.blocks.first_id()?)?;
+        let last = self.blocks.tip()?;
+        let elapsed = u64::try_from(last.timestamp.saturating_sub(first.timestamp)).unwrap_or(0);
+        Some(Duration::from_secs(elapsed))
+    }
+
+    // blocks added per hour over `chain_duration`, None if that is missing or zero
+    pub fn blocks_per_hour(&self) -> Option<f64> {
+        let duration = self
+            .chain_duration()
+            .filter(|duration| !duration.is_zero())?;
+        let intervals = (self.blocks.len() - 1) as f64;
+        Some(intervals * 3600.0 / duration.as_secs_f64())
+    }
+
+    // id of every block paired with the difficulty it was mined at, as inferred from its hash,
+    // for plotting how the difficulty evolved
+    // a block that got lucky and beat its target counts with the zero bits it actually has
+    pub fn difficulty_over_time(&self) -> Vec<(u64, u32)> {
+        self.blocks
+            .iter_blocks()
+            .map(|block| (block.id, block.hash_zero_bits()))
+            .collect()
+    }
+
+    // id of the first block holding a transaction with the given `Transaction::hash`,
+    // along with its index in that block
+    pub fn find_transaction(&self, tx_hash: &str) -> Option<(u64, usize)> {
+        self.blocks.iter_blocks().find_map(|block| {
+            block
+                .transactions
+                .iter()
+                .position(|tx| tx.hash() == tx_hash)
+                .map(|index| (block.id, index))
+        })
+    }
+
+    // headers of every block on the chain, see `validate_headers`
+    pub fn headers(&self) -> Vec<BlockHeader> {
+        self.blocks.iter_blocks().map(BlockHeader::from).collect()
+    }
+
+    // checks what can be checked without the payloads: ids are sequential, every header links to
+    // the one before it and, on proof of work chains, every hash after genesis meets the
+    // `min_difficulty`, what retargeting asked of each depends on the full blocks
+    // the hashes themselves can only be recomputed once the full blocks arrive
+    pub fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), BlockError> {
+        let proof_of_work = self.consensus == ConsensusMode::ProofOfWork;
+        // a legacy prefix is checked instead of the difficulty, like in `check_consensus`
+        let (difficulty, prefix) = match &self.difficulty_prefix {
+            Some(prefix) if proof_of_work => (None, Some(prefix)),
+            _ if proof_of_work => (Some(self.min_difficulty), None),
+            _ => (None, None),
+        };
+        for pair in headers.windows(2) {
+            validate_header(&pair[1], &pair[0], difficulty)?;
+            if let Some(prefix) = prefix {
+                let hash =
+                    hex::decode(&pair[1].hash).map_err(|_| BlockError::InvalidHashEncoding)?;
+                if !meets_prefix(&hash, prefix) {
+                    warn!("header id: {}, has an invalid difficulty", pair[1].id);
+                    return Err(BlockError::InvalidDifficulty);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // coins minted so far, the sum of every block reward, saturating at u64::MAX
+    // the genesis block only mints its allocations, see `genesis_with_allocations`,
+    // and blocks dropped by `prune` are no longer counted
+    pub fn total_supply(&self) -> u64 {
+        self.blocks
+            .iter_blocks()
+            .flat_map(|block| {
+                // fees only move coins that already exist
+                let fees = block
+                    .transactions
+                    .iter()
+                    .fold(0u64, |fees, tx| fees.saturating_add(tx.fee));
+                genesis_allocations(block)
+                    .into_values()
+                    .chain(std::iter::once(block.reward.saturating_sub(fees)))
+            })
+            .try_fold(0u64, |supply, amount| supply.checked_add(amount))
+            .unwrap_or(u64::MAX)
+    }
+
+    // net balance of `address` after the tip, credits minus debits
+    // block rewards count as credits for whoever mined or validated the block
+    pub fn balance_of(&self, address: &str) -> i64 {
+        let balance = self.balances.get(address).copied().unwrap_or(0);
+        balance.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
+    }
+
+    // drops all but the last `keep_last` blocks to cap memory use, at least the tip is always kept
+    // the dropped blocks are summarized in `pruned_state_root`, which chains the hash of every
+    // pruned block onto the previous root, and the last of them stays around as `pruned_tip`
+    pub fn prune(&mut self, keep_last: usize) {
+        let keep_last = keep_last.max(1);
+        if self.blocks.len() <= keep_last {
+            return;
+        }
+        let pruned = self.blocks.remove_oldest(self.blocks.len() - keep_last);
+        // positions shift down by the number of dropped blocks
+        self.last_validated_height = self
+            .last_validated_height
+            .saturating_sub(pruned.len() as u64);
+
+        let mut root = self.pruned_state_root.take().unwrap_or_default();
+        let mut retarget = self.retarget_start();
+        for block in &pruned {
+            self.advance_retarget(&mut retarget, block);
+            apply_block(&mut self.pruned_balances, block);
+            confirm_block(&mut self.pruned_confirmed_txs, block);
+            let mut hasher = Sha256::new();
+            hasher.update(root.as_bytes());
+            hasher.update(block.hash.as_bytes());
+            root = hex::encode(hasher.finalize());
+        }
+        info!("pruned {} blocks, state root: {}", pruned.len(), root);
+        self.pruned_state_root = Some(root);
+        self.pruned_retarget = Some(retarget);
+        self.pruned_tip = pruned.into_iter().last();
+    }
+
+    // hash of every block recomputed from its contents, in chain order
+    pub fn recompute_hashes(&self) -> Vec<String> {
+        self.blocks
+            .iter_blocks()
+            .map(|block| {
+                hex::encode(calculate_hash(
+                    &block.contents(),
+                    block.nonce,
+                    self.hash_algorithm,
+                ))
+            })
+            .collect()
+    }
+
+    // ids of blocks that were changed after mining, i.e. whose stored hash no longer matches
+    // their contents, changing any field that goes into the hash is enough to show up here
+    pub fn find_tampered(&self) -> Vec<u64> {
+        self.blocks
+            .iter_blocks()
+            .zip(self.recompute_hashes())
+            .filter(|(block, hash)| block.hash != *hash)
+            .map(|(block, _)| block.id)
+            .collect()
+    }
+
+    // single fingerprint of the whole chain, cheap to compare before running full validation
+    // every block is hashed in order as its JSON encoding rather than by its stored hash,
+    // so a block whose data was changed without re-mining still changes the fingerprint
+    pub fn chain_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for block in self.blocks.iter_blocks() {
+            let json = serde_json::to_string(block).expect("block can be serialized");
+            hasher.update(Sha256::digest(json.as_bytes()));
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    // single hash committing to every block hash in order along with the difficulty the block
+    // was mined at, see `difficulty_over_time`, each step hashes the previous commitment,
+    // the block hash and its zero bits, so reordering blocks or changing any of them changes it
+    // a light client compares it against a trusted value with `verify_work_proof`
+    pub fn work_proof(&self) -> String {
+        let mut proof = [0u8; 32];
+        for block in self.blocks.iter_blocks() {
+            let mut hasher = Sha256::new();
+            hasher.update(proof);
+            hasher.update(block.hash.as_bytes());
+            hasher.update(block.hash_zero_bits().to_be_bytes());
+            proof.copy_from_slice(&hasher.finalize());
+        }
+        hex::encode(proof)
+    }
+
+    // writes the chain to `path` as JSON so it survives restarts
+    pub fn save_to_file(&self, path: &Path) -> Result<(), BlockchainError> {
+        let blocks: Vec<&Block> = self.blocks.iter_blocks().collect();
+        let json = serde_json::to_string_pretty(&blocks)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    // like `load_from_file`, but validated with this app's settings, needed for every chain
+    // that wasn't made with the defaults, e.g. another hash algorithm, difficulty or consensus
+    pub fn with_file(self, path: &Path) -> Result<Self, BlockchainError> {
+        let contents = fs::read_to_string(path)?;
+        // an empty file holds no chain at all, which fails like any chain without a genesis
+        let blocks: Vec<Block> = if contents.trim().is_empty() {
+            vec![]
+        } else {
+            serde_json::from_str(&contents)?
+        };
+        Ok(self.with_blocks(blocks)?)
+    }
+
+    // like `from_blocks`, but validated with this app's settings, e.g. its checkpoints
+    // the blocks replace whatever the store held before
+    pub fn with_blocks(mut self, blocks: Vec<Block>) -> Result<Self, (usize, BlockError)> {
+        // the network is recorded in every block, the genesis says which one this chain is on,
+        // and when it was created unless the caller pinned `genesis_timestamp`
+        match blocks.first() {
+            Some(genesis) => {
+                self.network_id = genesis.network_id;
+                self.genesis_timestamp.get_or_insert(genesis.timestamp);
+            }
+            None => return Err((0, BlockError::MissingGenesis)),
+        }
+        self.validate_blocks(&blocks)?;
+        while self.blocks.pop_tip().is_some() {}
+        for block in blocks {
+            self.blocks.append(block);
+        }
+        self.balances = self.replay_balances();
+        self.confirmed_txs = self.replay_confirmed_txs();
+        self.mark_validated();
+        Ok(self)
+    }
+
+    // compact binary encoding of the chain, an alternative to the JSON written by `save_to_file`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let blocks: Vec<BinaryBlock> = self.blocks.iter_blocks().map(BinaryBlock::from).collect();
+        bincode::serialize(&blocks).expect("can serialize blocks")
+    }
+
+    // writes the chain as an archive meant for long term storage and other versions of this tool,
+    // a header of ARCHIVE_MAGIC, ARCHIVE_VERSION and the block count, then every block as bincode
+    // the integers in the header are little endian
+    pub fn export_archive(&self, mut w: impl Write) -> Result<(), BlockchainError> {
+        w.write_all(ARCHIVE_MAGIC)?;
+        w.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+        w.write_all(&(self.blocks.len() as u64).to_le_bytes())?;
+        for block in self.blocks.iter_blocks() {
+            bincode::serialize_into(&mut w, &BinaryBlock::from(block))?;
+        }
+        Ok(())
+    }
+
+    // like `import_archive`, but validated with this app's settings, see `with_file`
+    pub fn with_archive(self, mut r: impl Read) -> Result<Self, BlockchainError> {
+        let mut magic = [0u8; ARCHIVE_MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if magic != *ARCHIVE_MAGIC {
+            return Err(BlockchainError::NotAnArchive);
+        }
+        let mut version = [0u8; 2];
+        r.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != ARCHIVE_VERSION {
+            return Err(BlockchainError::UnsupportedArchiveVersion(version));
+        }
+        let mut count = [0u8; 8];
+        r.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+
+        let mut blocks = vec![];
+        for _ in 0..count {
+            let block: BinaryBlock = bincode::deserialize_from(&mut r)?;
+            blocks.push(Block::from(block));
+        }
+        Ok(self.with_blocks(blocks)?)
+    }
+
+    // like `from_bytes`, but validated with this app's settings, see `with_file`
+    pub fn with_bytes(self, bytes: &[u8]) -> Result<Self, BlockchainError> {
+        let blocks: Vec<BinaryBlock> = bincode::deserialize(bytes)?;
+        let blocks: Vec<Block> = blocks.into_iter().map(Block::from).collect();
+        Ok(self.with_blocks(blocks)?)
+    }
+
+    // writes one CSV row per block after a header row, for looking at the chain in a spreadsheet
+    pub fn export_csv(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "id,timestamp,nonce,hash,previous_hash,data")?;
+        for block in self.blocks.iter_blocks() {
+            writeln!(
+                w,
+                "{},{},{},{},{},{}",
+                block.id,
+                block.timestamp,
+                block.nonce,
+                escape_csv(&block.hash),
+                escape_csv(&block.previous_hash),
+                escape_csv(&block.data)
+            )?;
+        }
+        Ok(())
+    }
+
+    // chooses which chain to use
+    // an error is returned when neither chain is valid, the caller decides how to recover
+    pub fn choose_chain(
+        &mut self,
+        local: Vec<Block>,
+        remote: Vec<Block>,
+    ) -> Result<Vec<Block>, BlockchainError> {
+        // always choose the valid chain with the most work, the longest one when that is tied
+        // a remote chain that starts from a different genesis is on another network, however long
+        let is_local_valid = self.is_chain_valid(&local);
+        // so is one that would roll back more than `max_reorg_depth` local blocks
+        let is_remote_valid = self.is_chain_valid(&remote)
+            && same_genesis(local.first(), remote.first())
+            && self.within_reorg_depth(&remote);
+
+        if is_local_valid && is_remote_valid {
+            // when work and length are tied the numerically smaller tip hash wins, so every node
+            // ends up on the same chain no matter which one it saw first
+            let rank = |chain: &[Block]| {
+                let tip_hash = chain.last().and_then(|tip| hex::decode(&tip.hash).ok());
+                (App::total_work(chain), chain.len(), Reverse(tip_hash))
+            };
+            if rank(&local) >= rank(&remote) {
+                Ok(local)
+            } else {
+                Ok(remote)
+            }
+        } else if is_remote_valid && !is_local_valid {
+            Ok(remote)
+        } else if !is_remote_valid && is_local_valid {
+            Ok(local)
+        } else {
+            Err(ChainError::BothInvalid.into())
+        }
+    }
+
+    // splits the local chain and `other` at their last common block, for debugging forks
+    pub fn diff(&self, other: &[Block]) -> ChainDiff {
+        let (shared, shared_other) = self.shared_prefix(other);
+        ChainDiff {
+            common_ancestor: shared_other.checked_sub(1).map(|i| other[i].id),
+            only_in_self: self.blocks.iter_blocks().skip(shared).cloned().collect(),
+            only_in_other: other[shared_other..].to_vec(),
+        }
+    }
+
+    // positions in the local chain and in `chain` right after the last block both have in
+    // common, (0, 0) when they share none, blocks are lined up by id since either chain may
+    // have been pruned and start later than the other
+    fn shared_prefix(&self, chain: &[Block]) -> (usize, usize) {
+        let (local_first, first) = match (self.blocks.first_id(), chain.first()) {
+            (Some(local), Some(other)) => (local, local.max(other.id)),
+            _ => return (0, 0),
+        };
+        let start = usize::try_from(first - chain[0].id).unwrap_or(usize::MAX);
+        let shared = chain
+            .iter()
+            .skip(start)
+            .zip(first..)
+            .take_while(|(block, id)| {
+                self.blocks
+                    .get_block(*id)
+                    .is_some_and(|local| local.hash == block.hash)
+            })
+            .count();
+        if shared == 0 {
+            (0, 0)
+        } else {
+            ((first - local_first) as usize + shared, start + shared)
+        }
+    }
+
+    // whether switching to `chain` rolls back no more local blocks than `max_reorg_depth` allows
+    fn within_reorg_depth(&self, chain: &[Block]) -> bool {
+        let depth = self.blocks.len() - self.shared_prefix(chain).0;
+        match self.max_reorg_depth {
+            Some(max) if depth > max => {
+                warn!(
+                    "refusing a reorg {} blocks deep, at most {} allowed",
+                    depth, max
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    // replaces the local chain with `new_chain`, keeping the blocks both share,
+    // transactions from rolled back blocks that are not part of the new chain go back to the mempool
+    // fails with ReorgTooDeep if that would roll back more than `max_reorg_depth` blocks,
+    // with DifferentGenesis for a chain from another network, like `choose_chain` refuses it,
+    // and with MissingGenesis for an empty chain, which would leave nothing to build on
+    // a new chain starting at genesis replaces whatever `prune` dropped as well, the pruned
+    // state is cleared and balances are replayed from it
+    pub fn reorg_to(&mut self, new_chain: Vec<Block>) -> Result<ReorgReport, BlockchainError> {
+        if new_chain.is_empty() {
+            return Err((0, BlockError::MissingGenesis).into());
+        }
+        if !same_genesis(self.blocks.get_block(0), new_chain.first()) {
+            return Err(ChainError::DifferentGenesis.into());
+        }
+        if !self.within_reorg_depth(&new_chain) {
+            return Err(ChainError::ReorgTooDeep.into());
+        }
+        if !self.is_chain_valid(&new_chain) {
+            return Err(ChainError::InvalidChain.into());
+        }
+
+        let (shared_local, shared) = self.shared_prefix(&new_chain);
+        let common_ancestor = shared.checked_sub(1).map(|i| new_chain[i].id);
+
+        let mut rolled_back = vec![];
+        while self.blocks.len() > shared_local {
+            match self.blocks.pop_tip() {
+                Some(block) => rolled_back.push(block),
+                None => break,
+            }
+        }
+        rolled_back.reverse();
+        let added = new_chain.len() - shared;
+        for block in rolled_back.iter().rev() {
+            revert_block(&mut self.balances, block);
+            for tx in &block.transactions {
+                self.confirmed_txs.remove(&tx.hash());
+            }
+        }
+        for block in &new_chain[shared..] {
+            apply_block(&mut self.balances, block);
+            confirm_block(&mut self.confirmed_txs, block);
+        }
+
+        for tx in rolled_back.iter().flat_map(|block| &block.transactions) {
+            let confirmed = new_chain[shared..]
+                .iter()
+                .any(|block| block.transactions.contains(tx));
+            if !confirmed && !self.mempool.contains(tx) {
+                self.mempool.push(tx.clone());
+            }
+        }
+        // drop pending transactions the new chain already confirmed
+        self.mempool.retain(|tx| {
+            !new_chain[shared..]
+                .iter()
+                .any(|block| block.transactions.contains(tx))
+        });
+
+        // the old chain's cache doesn't apply to the new one, but that was just validated in full
+        // one from genesis brings back what `prune` dropped, so it is stored as a whole
+        let from_genesis = self.pruned_tip.is_some() && new_chain[0].id == 0;
+        if from_genesis {
+            while self.blocks.pop_tip().is_some() {}
+        }
+        let kept = if from_genesis { 0 } else { shared };
+        for block in new_chain.into_iter().skip(kept) {
+            self.blocks.append(block);
+        }
+        if from_genesis {
+            self.pruned_tip = None;
+            self.pruned_state_root = None;
+            self.pruned_balances.clear();
+            self.pruned_confirmed_txs.clear();
+            self.pruned_retarget = None;
+            self.balances = self.replay_balances();
+            self.confirmed_txs = self.replay_confirmed_txs();
+        }
+        self.mark_validated();
+        info!(
+            "reorg: rolled back {} blocks, added {}",
+            rolled_back.len(),
+            added
+        );
+        let report = ReorgReport {
+            common_ancestor,
+            rolled_back: rolled_back.len(),
+            added,
+        };
+        self.notify(ChainEvent::Reorg(report.clone()));
+        Ok(report)
+    }
+
+    // balance of every address after the tip, including the pruned part of the chain,
+    // computed from scratch by replaying every block
+    pub fn replay_balances(&self) -> HashMap<String, i128> {
+        let mut balances = self.pruned_balances.clone();
+        if let (Some(first), Some(tip)) = (self.blocks.first_id(), self.blocks.tip()) {
+            for block in (first..=tip.id).filter_map(|id| self.blocks.get_block(id)) {
+                apply_block(&mut balances, block);
+            }
+        }
+        balances
+    }
+
+    // hash of every transaction on the chain, starting from the pruned ones
+    fn replay_confirmed_txs(&self) -> HashSet<String> {
+        let mut confirmed = self.pruned_confirmed_txs.clone();
+        if let (Some(first), Some(tip)) = (self.blocks.first_id(), self.blocks.tip()) {
+            for block in (first..=tip.id).filter_map(|id| self.blocks.get_block(id)) {
+                confirm_block(&mut confirmed, block);
+            }
+        }
+        confirmed
+    }
+
+    // replays the chain like `replay_balances` and finds the first transaction with a bad
+    // signature or spending more than its sender has at that point, as the id of its block,
+    // its index in the block and why, for debugging a chain, None when every one is fine
+    // unlike validation it doesn't stop at invalid blocks, only transactions are looked at
+    pub fn first_invalid_transaction(&self) -> Option<(u64, usize, TxError)> {
+        let mut balances = self.pruned_balances.clone();
+        let (first, tip) = (self.blocks.first_id()?, self.blocks.tip()?.id);
+        for block in (first..=tip).filter_map(|id| self.blocks.get_block(id)) {
+            let bad_signature = block
+                .transactions
+                .iter()
+                .position(|tx| !tx.has_valid_signature())
+                .map(|index| (index, TxError::InvalidSignature));
+            let overspend = spend_transactions(&block.transactions, &balances).err();
+            // whichever comes first, the funds check carries on through a bad signature
+            if let Some((index, e)) = bad_signature
+                .into_iter()
+                .chain(overspend)
+                .min_by_key(|(index, _)| *index)
+            {
+                return Some((block.id, index, e));
+            }
+            apply_block(&mut balances, block);
+        }
+        None
+    }
+
+    // creates the first, hard-coded, block in blockchain
+    // special block, which is the start of the blockchain
+    // must run exactly once, on an empty chain, before any other block is added
+    // the timestamp is fixed and the hash is computed, so every node ends up with the same genesis
+    fn genesis(&mut self) {
+        let timestamp = *self.genesis_timestamp.get_or_insert(GENESIS_TIMESTAMP);
+        let hash = calculate_hash(
+            &BlockContents {
+                network_id: self.network_id,
+                ..BlockContents::new(0, timestamp, "genesis", "genesis")
+            },
+            GENESIS_NONCE,
+            self.hash_algorithm,
+        );
+        let genesis_block = Block {
+            id: 0,
+            timestamp,
+            previous_hash: "genesis".to_string(),
+            data: "genesis".to_string(),
+            nonce: GENESIS_NONCE,
+            reward: 0,
+            transactions: vec![],
+            merkle_root: None,
+            validator: None,
+            miner: None,
+            network_id: self.network_id,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+            hash: hex::encode(hash),
+        };
+        self.push_block(genesis_block);
+    }
+
+    // like `genesis`, but with custom data and timestamp so every network can have its own
+    // the nonce is mined against `difficulty` instead of being hard-coded
+    // `timestamp` becomes the `genesis_timestamp`, other nodes have to be configured with it too
+    pub fn genesis_with(&mut self, data: String, timestamp: i64) -> Result<(), BlockchainError> {
+        if !self.blocks.is_empty() {
+            warn!("refusing to create a second genesis block");
+            return Err(ChainError::GenesisExists.into());
+        }
+        self.genesis_timestamp = Some(timestamp);
+        let previous_hash = "genesis".to_string();
+        let result = MiningJob {
+            contents: BlockContents {
+                network_id: self.network_id,
+                ..BlockContents::new(0, timestamp, &previous_hash, &data)
+            },
+            difficulty: self.difficulty,
+            algorithm: self.hash_algorithm,
+            prefix: None,
+        }
+        .search(0..=u64::MAX, || false)
+        .expect("nonce space exhausted");
+
+        self.push_block(Block {
+            id: 0,
+            hash: result.hash,
+            previous_hash,
+            timestamp,
+            data,
+            nonce: result.nonce,
+            reward: 0,
+            transactions: vec![],
+            merkle_root: None,
+            validator: None,
+            miner: None,
+            network_id: self.network_id,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+        });
+        Ok(())
+    }
+
+    // genesis block that starts the chain with the given balances, e.g. for experimenting
+    // with an initial distribution, they are written into its data and count towards `balance_of`
+    pub fn genesis_with_allocations(
+        &mut self,
+        allocations: HashMap<String, u64>,
+    ) -> Result<(), BlockchainError> {
+        let data = serde_json::to_string(&GenesisData {
+            allocations: allocations.into_iter().collect(),
+        })
+        .expect("can serialize allocations");
+        self.genesis_with(data, self.genesis_timestamp.unwrap_or(GENESIS_TIMESTAMP))
+    }
+
+    // number of blocks in the chain, genesis included
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // looks a block up by its id rather than its position in `blocks`
+    pub fn get(&self, id: u64) -> Option<&Block> {
+        self.blocks.get_block(id)
+    }
+
+    // proof that transaction `tx_index` of block `block_id` is committed to by its merkle root,
+    // checked with `verify_inclusion` by anyone who only has the block header and the transaction
+    pub fn inclusion_proof(&self, block_id: u64, tx_index: usize) -> Option<Vec<String>> {
+        let block = self.get(block_id)?;
+        merkle_proof(&merkle_leaves(&block.transactions), tx_index)
+    }
+
+    pub fn get_tip(&self) -> Option<Block> {
+        self.blocks.tip().cloned()
+    }
+
+    // whether the tip is older than `max_age`, e.g. to warn when no blocks have come in for a while
+    // an empty chain has no tip at all and always counts as stale
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        match self.blocks.tip() {
+            Some(tip) => {
+                let age = Utc::now().timestamp().saturating_sub(tip.timestamp);
+                u64::try_from(age).is_ok_and(|age| age > max_age.as_secs())
+            }
+            None => true,
+        }
+    }
+
+    pub fn get_chain_info(&self) -> ChainInfo {
+        let tip = self.blocks.tip();
+        ChainInfo {
+            height: tip.map_or(0, |block| block.id),
+            tip_hash: tip.map(|block| block.hash.clone()),
+            difficulty: self.next_difficulty(),
+        }
+    }
+
+    // gets last block in the chain, validate if block is valid and can be added
+    // a block whose parent isn't in the chain yet is kept in the orphan pool instead,
+    // it gets connected as soon as the parent is added
+    // an app without a genesis block, e.g. `App::new()`, rejects every block with MissingGenesis
+    pub fn try_add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
+        self.add_block(block).map_err(|e| {
+            self.notify(ChainEvent::BlockRejected(e.clone()));
+            e.into()
+        })
+    }
+
+    fn add_block(&mut self, block: Block) -> Result<(), BlockError> {
+        let latest_block = match self.blocks.tip() {
+            Some(latest_block) => latest_block,
+            None => {
+                warn!(
+                    "block id: {}, can't be added to a chain without genesis",
+                    block.id
+                );
+                return Err(BlockError::MissingGenesis);
+            }
+        };
+        if block.previous_hash != latest_block.hash
+            && !self.blocks.contains_hash(&block.previous_hash)
+        {
+            // only blocks that are consistent on their own and did the work get buffered,
+            // the difficulty they are held to is the minimum, the parent's history is unknown
+            block.validate_fields()?;
+            block.verify_self(Difficulty::ZERO, self.hash_algorithm)?;
+            self.check_consensus(&block, self.min_difficulty)?;
+            if self.orphan_pool.values().map(Vec::len).sum::<usize>() >= self.max_orphans {
+                warn!("block id: {}, dropped, the orphan pool is full", block.id);
+                return Err(BlockError::OrphanPoolFull);
+            }
+            info!(
+                "block id: {}, has no known parent yet, keeping it as an orphan",
+                block.id
+            );
+            self.orphan_pool
+                .entry(block.previous_hash.clone())
+                .or_default()
+                .push(block);
+            return Err(BlockError::Orphan);
+        }
+        self.is_block_valid(
+            &block,
+            latest_block,
+            &self.balances,
+            &self.confirmed_txs,
+            self.next_difficulty(),
+        )?;
+        self.push_block(block);
+        self.mark_validated();
+        self.connect_orphans();
+        Ok(())
+    }
+
+    // parses a block received from outside and adds it like `try_add_block` does
+    pub fn accept_external_block(&mut self, json: &str) -> Result<(), BlockchainError> {
+        let block = Block::from_json(json).inspect_err(|e| {
+            warn!("rejected external block: {}", e);
+        })?;
+        self.try_add_block(block)
+    }
+
+    // appends buffered orphans for as long as one of them extends the tip
+    fn connect_orphans(&mut self) {
+        loop {
+            let latest_block = self.blocks.tip().expect("there is atleast a single block");
+            let children = match self.orphan_pool.remove(&latest_block.hash) {
+                Some(children) => children,
+                None => return,
+            };
+            let difficulty = self.next_difficulty();
+            match children.into_iter().find(|child| {
+                self.is_block_valid(
+                    child,
+                    latest_block,
+                    &self.balances,
+                    &self.confirmed_txs,
+                    difficulty,
+                )
+                .is_ok()
+            }) {
+                Some(child) => {
+                    info!("block id: {}, connected from the orphan pool", child.id);
+                    self.push_block(child);
+                    self.mark_validated();
+                }
+                None => return,
+            }
+        }
+    }
+
+    // validating block logic
+    // ensures blockchain adheres to chain property & is hard to tamper with
+    // returns the reason the block was rejected, never panics on malformed input
+    // `balances` are the balances after `previous_block`, see `validate_block_transactions`,
+    // and `confirmed` the hashes of the transactions up to it, none of them may come again
+    // `difficulty` is what `retarget` gives for the blocks up to `previous_block`
+    fn is_block_valid(
+        &self,
+        block: &Block,
+        previous_block: &Block,
+        balances: &HashMap<String, i128>,
+        confirmed: &HashSet<String>,
+        difficulty: Difficulty,
+    ) -> Result<(), BlockError> {
+        block.validate_fields()?;
+        self.check_checkpoint(block)?;
+        self.check_data(&block.data, &block.transactions)?;
+        if block.transactions.len() > self.max_txs_per_block {
+            warn!(
+                "block id: {}, has {} transactions",
+                block.id,
+                block.transactions.len()
+            );
+            return Err(BlockError::TooManyTransactions);
+        }
+        if block.encoded_len() > self.max_block_bytes {
+            warn!("block id: {}, is {} bytes", block.id, block.encoded_len());
+            return Err(BlockError::BlockTooLarge);
+        }
+        if block.network_id != self.network_id {
+            warn!(
+                "block id: {}, belongs to network {}",
+                block.id, block.network_id
+            );
+            return Err(BlockError::WrongNetwork);
+        }
+        if block.previous_hash != previous_block.hash {
+            warn!("block id: {}, has an invalid previous hash", block.id);
+            return Err(BlockError::PreviousHashMismatch);
+        }
+        self.check_consensus(block, difficulty)?;
+        if block.timestamp < previous_block.timestamp {
+            warn!("block id: {}, is older than its previous block", block.id);
+            return Err(BlockError::TimestampBeforeParent);
+        }
+        let min_interval = i64::try_from(self.min_interval.as_secs()).unwrap_or(i64::MAX);
+        if block.timestamp.saturating_sub(previous_block.timestamp) < min_interval {
+            warn!(
+                "block id: {}, came less than {}s after its previous block",
+                block.id, min_interval
+            );
+            return Err(BlockError::TooSoonAfterParent);
+        }
+        if let Some(drift) = self.max_future_drift {
+            let drift = i64::try_from(drift.as_secs()).unwrap_or(i64::MAX);
+            if block.timestamp > Utc::now().timestamp().saturating_add(drift) {
+                warn!(
+                    "block id: {}, has a timestamp too far in the future",
+                    block.id
+                );
+                return Err(BlockError::TimestampInFuture);
+            }
+        }
+        if previous_block.id.checked_add(1) != Some(block.id) {
+            warn!(
+                "block id: {}, is not the block after the latest: {}",
+                block.id, previous_block.id
+            );
+            return Err(BlockError::NonSequentialId);
+        } else if block.reward != App::expected_reward(block.id, &block.transactions) {
+            warn!("block id: {}, has an invalid reward", block.id);
+            return Err(BlockError::InvalidReward);
+        } else if !block
+            .transactions
+            .iter()
+            .all(Transaction::has_valid_signature)
+        {
+            warn!("block id: {}, has an invalid transaction", block.id);
+            return Err(BlockError::InvalidTransaction);
+        }
+        App::validate_block_transactions(block, balances)?;
+        if !block.has_valid_signature() {
+            warn!("block id: {}, has an invalid signature", block.id);
+            return Err(BlockError::InvalidSignature);
+        }
+        if block.merkle_root != expected_merkle_root(&block.transactions) {
+            warn!("block id: {}, has an invalid merkle root", block.id);
+            return Err(BlockError::MerkleRootMismatch);
+        }
+        // transactions carry no nonce, a signed one stays valid and could be mined over and over
+        let mut seen = HashSet::new();
+        if let Some(tx) = block.transactions.iter().find(|tx| {
+            let hash = tx.hash();
+            confirmed.contains(&hash) || !seen.insert(hash)
+        }) {
+            warn!("block id: {}, repeats transaction {}", block.id, tx.hash());
+            return Err(BlockError::ReplayedTransaction);
+        }
+        for rule in &self.validation_rules {
+            rule(block, previous_block).inspect_err(|e| {
+                warn!("block id: {}, breaks a validation rule: {}", block.id, e);
+            })?;
+        }
+        Ok(())
+    }
+
+    // registers a check every block after genesis has to pass on top of the built-in ones,
+    // e.g. that its data is valid JSON, a rule without a fitting error can use RuleViolation
+    pub fn add_validation_rule(
+        &mut self,
+        rule: impl Fn(&Block, &Block) -> Result<(), BlockError> + Send + Sync + 'static,
+    ) {
+        self.validation_rules.push(Box::new(rule));
+    }
+
+    // the original way to set the difficulty, a string of 0s and 1s the hash written out in binary
+    // has to start with, e.g. "00", mining and validation check it instead of `difficulty`,
+    // which could contradict a prefix with a 1 in it
+    pub fn set_difficulty_prefix(&mut self, prefix: &str) -> Result<(), ConfigError> {
+        if prefix.is_empty() {
+            return Err(ConfigError::EmptyDifficultyPrefix);
+        }
+        if let Some(c) = prefix.chars().find(|c| *c != '0' && *c != '1') {
+            return Err(ConfigError::InvalidDifficultyPrefix(c));
+        }
+        // only 0s and 1s are left, so the length in bytes is the number of bits
+        if prefix.len() > HASH_BITS as usize {
+            return Err(ConfigError::DifficultyPrefixTooLong(prefix.len()));
+        }
+        self.difficulty_prefix = Some(prefix.to_string());
+        Ok(())
+    }
+
+    // maximum acceptable hash value for `difficulty`, the one the chain starts at,
+    // see `Difficulty::target`
+    pub fn difficulty_target(&self) -> [u8; 32] {
+        self.difficulty.target()
+    }
+
+    // the stored nonce has to produce the stored hash from the block contents, HashMismatch if
+    // it doesn't, and only then is that hash held against the consensus rules:
+    // proof of work blocks have to meet the difficulty, InvalidDifficulty if they don't,
+    // proof of stake blocks have to come from the validator selected for them
+    fn check_consensus(&self, block: &Block, difficulty: Difficulty) -> Result<(), BlockError> {
+        let hash = match hex::decode(&block.hash) {
+            Ok(hash) => hash,
+            Err(_) => {
+                warn!("block id: {}, has a hash which is not hex", block.id);
+                return Err(BlockError::InvalidHashEncoding);
+            }
+        };
+        let computed = calculate_hash(&block.contents(), block.nonce, self.hash_algorithm);
+        if hex::encode(computed) != block.hash {
+            warn!(
+                "block id: {}, nonce {} does not produce its hash",
+                block.id, block.nonce
+            );
+            return Err(BlockError::HashMismatch);
+        }
+        match self.consensus {
+            // every hash meets a zero difficulty
+            ConsensusMode::ProofOfWork
+                if difficulty.is_zero() && self.difficulty_prefix.is_none() => {}
+            ConsensusMode::ProofOfWork => {
+                let meets = match &self.difficulty_prefix {
+                    Some(prefix) => meets_prefix(&hash, prefix),
+                    None => meets_target(&hash, &difficulty.target()),
+                };
+                if !meets {
+                    warn!("block id: {}, has an invalid difficulty", block.id);
+                    return Err(BlockError::InvalidDifficulty);
+                }
+            }
+            ConsensusMode::ProofOfStake => {
+                let selected = self.select_validator(&block.previous_hash, block.id);
+                if selected.is_none() || block.validator != selected {
+                    warn!("block id: {}, has an invalid validator", block.id);
+                    return Err(BlockError::InvalidValidator);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // picks the validator of block `id` following `previous_hash`, weighted by stake
+    // the pick is derived from the hash of both, so every node selects the same validator
+    pub fn select_validator(&self, previous_hash: &str, id: u64) -> Option<String> {
+        let total: u128 = self.stakes.values().map(|stake| u128::from(*stake)).sum();
+        if total == 0 {
+            return None;
+        }
+        let seed = Sha256::digest(format!("{}{}", previous_hash, id).as_bytes());
+        let mut seed_bytes = [0u8; 16];
+        seed_bytes.copy_from_slice(&seed[..16]);
+        let mut pick = u128::from_be_bytes(seed_bytes) % total;
+
+        for (validator, stake) in &self.stakes {
+            let stake = u128::from(*stake);
+            if pick < stake {
+                return Some(validator.clone());
+            }
+            pick -= stake;
+        }
+        None
+    }
+
+    // marks every local block as validated, for after the chain was checked or extended
+    fn mark_validated(&mut self) {
+        self.last_validated_height = self.blocks.len().saturating_sub(1) as u64;
+    }
+
+    // index of the first block in `chain` that fails validation, None if the whole chain is valid
+    // the first block has no predecessor, it only has to be a genesis block matching its own hash,
+    // or, once the chain has been pruned, a valid successor of the last pruned block
+    pub fn find_invalid_block(&self, chain: &[Block]) -> Option<usize> {
+        self.validate_blocks(chain).err().map(|(index, _)| index)
+    }
+
+    // validates blocks one at a time as the iterator yields them, e.g. read lazily from disk
+    // only the previous block and the balances are kept around, not the chain itself
+    // returns how many blocks were validated, or the index and reason of the first invalid one
+    pub fn validate_stream(
+        &self,
+        blocks: impl Iterator<Item = Block>,
+    ) -> Result<usize, (usize, BlockError)> {
+        self.validate_blocks(blocks)
+    }
+
+    fn validate_blocks<B: Borrow<Block>>(
+        &self,
+        blocks: impl IntoIterator<Item = B>,
+    ) -> Result<usize, (usize, BlockError)> {
+        self.validate_blocks_from(blocks, 0)
+    }
+
+    // like `validate_blocks`, but the first `trusted` blocks are known to be valid
+    // and only replayed for the balances
+    fn validate_blocks_from<B: Borrow<Block>>(
+        &self,
+        blocks: impl IntoIterator<Item = B>,
+        trusted: usize,
+    ) -> Result<usize, (usize, BlockError)> {
+        let mut balances = HashMap::new();
+        let mut confirmed = HashSet::new();
+        // where retargeting stands for the next block
+        let mut retarget = self.initial_retarget();
+        let mut previous: Option<B> = None;
+        let mut count = 0;
+        for block in blocks {
+            let anchor = match (&previous, &self.pruned_tip) {
+                (Some(previous), _) => Some(previous.borrow()),
+                (None, Some(pruned_tip)) if block.borrow().id != 0 => {
+                    balances = self.pruned_balances.clone();
+                    confirmed = self.pruned_confirmed_txs.clone();
+                    retarget = self.retarget_start();
+                    Some(pruned_tip)
+                }
+                (None, _) => None,
+            };
+            if count >= trusted {
+                let result = match anchor {
+                    Some(previous) => self.is_block_valid(
+                        block.borrow(),
+                        previous,
+                        &balances,
+                        &confirmed,
+                        self.retarget(&retarget),
+                    ),
+                    None => self.is_genesis_valid(block.borrow()),
+                };
+                result.map_err(|e| (count, e))?;
+            } else {
+                // a checkpoint may have been set after the trusted blocks were validated
+                self.check_checkpoint(block.borrow())
+                    .map_err(|e| (count, e))?;
+            }
+            apply_block(&mut balances, block.borrow());
+            confirm_block(&mut confirmed, block.borrow());
+            self.advance_retarget(&mut retarget, block.borrow());
+            previous = Some(block);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // pins the block at `id` to `hash`, from now on a chain with a different block there,
+    // however long it is, fails validation
+    pub fn set_checkpoint(&mut self, id: u64, hash: String) {
+        self.checkpoints.insert(id, hash);
+    }
+
+    fn check_checkpoint(&self, block: &Block) -> Result<(), BlockError> {
+        match self.checkpoints.get(&block.id) {
+            Some(hash) if *hash != block.hash => {
+                warn!(
+                    "block id: {}, does not match the checkpoint {}",
+                    block.id, hash
+                );
+                Err(BlockError::CheckpointMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // appends an already validated block and credits it to `balances`
+    fn push_block(&mut self, block: Block) {
+        apply_block(&mut self.balances, &block);
+        confirm_block(&mut self.confirmed_txs, &block);
+        if !self.subscribers.is_empty() {
+            self.notify(ChainEvent::BlockAdded(Box::new(block.clone())));
+        }
+        self.blocks.append(block);
+    }
+
+    // receives a ChainEvent for every block added, reorg and rejected block from now on
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn notify(&mut self, event: ChainEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    // the genesis block is deliberately exempt from the difficulty, it has no parent it was
+    // mined against and may have been mined at a weaker one, e.g. the hard-coded GENESIS_NONCE,
+    // so raising `difficulty` only ever affects the blocks after it
+    // its timestamp has to be exactly `genesis_timestamp`, when that is pinned, and its hash has
+    // to follow from its contents, a checkpoint at id 0 pins the hash itself
+    fn is_genesis_valid(&self, block: &Block) -> Result<(), BlockError> {
+        block.validate_fields()?;
+        self.check_checkpoint(block)?;
+        if block.id != 0 {
+            warn!("block id: {}, is not a genesis block", block.id);
+            return Err(BlockError::NonSequentialId);
+        }
+        if block.network_id != self.network_id {
+            warn!("genesis block belongs to network {}", block.network_id);
+            return Err(BlockError::WrongNetwork);
+        }
+        match self.genesis_timestamp {
+            Some(timestamp) if block.timestamp != timestamp => {
+                warn!(
+                    "genesis block has timestamp {} instead of {}",
+                    block.timestamp, timestamp
+                );
+                return Err(BlockError::GenesisTimestampMismatch);
+            }
+            _ => {}
+        }
+        let hash = calculate_hash(&block.contents(), block.nonce, self.hash_algorithm);
+        if hex::encode(hash) != block.hash {
+            warn!("genesis block has an invalid hash");
+            return Err(BlockError::HashMismatch);
+        }
+        if !block.has_valid_signature() {
+            warn!("genesis block has an invalid signature");
+            return Err(BlockError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    // difficulty the next block will be mined with, and has to meet, see `retarget`
+    // every difficulty follows from the one before it, so retargeting is replayed over the chain
+    pub fn next_difficulty(&self) -> Difficulty {
+        let mut state = self.retarget_start();
+        for block in self.blocks.iter_blocks() {
+            self.advance_retarget(&mut state, block);
+        }
+        self.retarget(&state)
+    }
+
+    // where retargeting stands before the first block after genesis
+    fn initial_retarget(&self) -> RetargetState {
+        RetargetState {
+            difficulty: self.difficulty,
+            window: VecDeque::with_capacity(RETARGET_WINDOW),
+        }
+    }
+
+    // where retargeting stands before the first block held, after the pruned ones
+    fn retarget_start(&self) -> RetargetState {
+        self.pruned_retarget
+            .clone()
+            .unwrap_or_else(|| self.initial_retarget())
+    }
+
+    // moves `state` past `block`, which had to meet what `retarget` gives for `state`
+    fn advance_retarget(&self, state: &mut RetargetState, block: &Block) {
+        // the genesis timestamp is fixed rather than mined, so it is left out of the window
+        if block.id == 0 {
+            return;
+        }
+        state.difficulty = self.retarget(state);
+        if state.window.len() == RETARGET_WINDOW {
+            state.window.pop_front();
+        }
+        state.window.push_back(block.timestamp);
+    }
+
+    // difficulty of the block after the one `state` was last moved past
+    // it starts out at `difficulty` and moves from block to block by what the window says: only
+    // the median time between its blocks counts, a single outlier can't move it the way it moves
+    // an average, every bit is a factor of two, one is added for each halving of the target time
+    // the median undercuts and one is taken away for each doubling it overshoots, at most
+    // `max_retarget_factor` either way per block and never below `min_difficulty`
+    fn retarget(&self, state: &RetargetState) -> Difficulty {
+        // zero difficulty is for tests, retargeting would only make them slower
+        if self.difficulty.is_zero() {
+            return self.difficulty;
+        }
+        let window = &state.window;
+        if window.len() < 2 {
+            return state.difficulty.max(self.min_difficulty);
+        }
+        let target = i64::try_from(self.target_block_time.as_secs()).unwrap_or(i64::MAX);
+        let max_bits = self.max_retarget_factor.checked_ilog2().unwrap_or(0);
+        let mut intervals: Vec<i64> = window
+            .iter()
+            .zip(window.iter().skip(1))
+            .map(|(earlier, later)| later.saturating_sub(*earlier))
+            .collect();
+        intervals.sort_unstable();
+        let median = intervals[intervals.len() / 2];
+
+        let mut up = 0;
+        while up < max_bits && median.saturating_mul(1 << (up + 1)) < target {
+            up += 1;
+        }
+        let mut down = 0;
+        while down < max_bits && median > target.saturating_mul(1 << (down + 1)) {
+            down += 1;
+        }
+        let bits = state
+            .difficulty
+            .bits()
+            .saturating_add(up)
+            .saturating_sub(down);
+        Difficulty::from_bits(bits.max(self.min_difficulty.bits()))
+    }
+
+    // how long mining the next block will probably take at the given hash rate
+    // on average a hash meets a difficulty of n bits once every 2^n tries
+    pub fn estimated_mine_seconds(&self, hashes_per_sec: f64) -> f64 {
+        if hashes_per_sec <= 0.0 {
+            return f64::INFINITY;
+        }
+        let expected_hashes = 2f64.powf(f64::from(self.next_difficulty().bits()));
+        expected_hashes / hashes_per_sec
+    }
+
+    // hashes per second this machine manages with `hash_algorithm`, measured by mining a block
+    // nobody can find for about `duration`, e.g. to feed `estimated_mine_seconds`
+    // the clock is only read every BENCHMARK_CHECK_INTERVAL nonces, so it overshoots very little
+    pub fn benchmark_hashrate(&self, duration: Duration) -> f64 {
+        let job = MiningJob {
+            contents: BlockContents::new(0, 0, "benchmark", "benchmark"),
+            difficulty: Difficulty::from_bits(u32::MAX),
+            algorithm: self.hash_algorithm,
+            prefix: None,
+        };
+        let started = Instant::now();
+        let mut calls: u64 = 0;
+        job.search_with_progress(
+            0..=u64::MAX,
+            || {
+                calls += 1;
+                calls.is_multiple_of(BENCHMARK_CHECK_INTERVAL) && started.elapsed() >= duration
+            },
+            |_| {},
+        );
+        // the last call stopped the search instead of letting a nonce be hashed
+        let hashes = calls.saturating_sub(1);
+        hashes as f64 / started.elapsed().as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    pub fn target_block_time(&self) -> Duration {
+        self.target_block_time
+    }
+
+    // mines the block that follows the current tip, using the retargeted difficulty
+    // under proof of stake the block is produced by the selected validator instead
+    // panics without a genesis block, under proof of stake without stakers, or when the app
+    // refuses `data`, `try_next_block` returns those as errors
+    pub fn next_block(&self, data: String) -> Block {
+        self.try_next_block(data)
+            .expect("there is atleast a single block, a validator and data the app takes")
+    }
+
+    // like `next_block`, but fails without mining when the app refuses `data`, see `check_data`,
+    // with MissingGenesis on an empty chain and with InvalidValidator under proof of stake when
+    // nobody has stake to produce the block
+    pub fn try_next_block(&self, data: String) -> Result<Block, BlockError> {
+        self.next_block_with_transactions(data, vec![])
+    }
+
+    // `data` may be at most `max_data_bytes` long, in bytes of its UTF-8 encoding rather than
+    // chars, and only empty with transactions or while `allow_empty_data` is on
+    fn check_data(&self, data: &str, transactions: &[Transaction]) -> Result<(), BlockError> {
+        if data.len() > self.max_data_bytes {
+            warn!("refusing a block with {} bytes of data", data.len());
+            return Err(BlockError::DataTooLarge);
+        }
+        if !self.allow_empty_data && data.is_empty() && transactions.is_empty() {
+            warn!("refusing a block without data");
+            return Err(BlockError::EmptyData);
+        }
+        Ok(())
+    }
+
+    fn next_block_with_transactions(
+        &self,
+        data: String,
+        transactions: Vec<Transaction>,
+    ) -> Result<Block, BlockError> {
+        self.check_data(&data, &transactions)?;
+        let latest_block = self.blocks.tip().ok_or(BlockError::MissingGenesis)?;
+        let id = latest_block.id + 1;
+        match self.consensus {
+            ConsensusMode::ProofOfWork => {
+                let mut builder = Block::builder()
+                    .id(id)
+                    .previous_hash(latest_block.hash.clone())
+                    .data(data)
+                    .transactions(transactions)
+                    .difficulty(self.next_difficulty())
+                    .network_id(self.network_id)
+                    .hash_algorithm(self.hash_algorithm);
+                if let Some(miner) = &self.miner_address {
+                    builder = builder.miner(miner.clone());
+                }
+                if let Some(timestamp) = self.seed_timestamp {
+                    builder = builder.timestamp(timestamp);
+                }
+                if let Some(prefix) = &self.difficulty_prefix {
+                    builder = builder.difficulty_prefix(prefix.clone());
+                }
+                Ok(builder.build())
+            }
+            ConsensusMode::ProofOfStake => {
+                let validator = self
+                    .select_validator(&latest_block.hash, id)
+                    .ok_or(BlockError::InvalidValidator)?;
+                let mut block = Block::new_staked(
+                    id,
+                    latest_block.hash.clone(),
+                    data,
+                    transactions,
+                    validator,
+                    self.network_id,
+                    self.hash_algorithm,
+                );
+                // staked blocks have no nonce, the hash only has to follow the new timestamp
+                if let Some(timestamp) = self.seed_timestamp {
+                    block.timestamp = timestamp;
+                    block.hash = hex::encode(calculate_hash(
+                        &block.contents(),
+                        block.nonce,
+                        self.hash_algorithm,
+                    ));
+                }
+                Ok(block)
+            }
+        }
+    }
+
+    // queues a transaction for the next mined block, its signature is checked up front
+    pub fn add_to_mempool(&mut self, tx: Transaction) -> Result<(), BlockError> {
+        if !tx.has_valid_signature() {
+            warn!(
+                "rejected transaction from {} with an invalid signature",
+                tx.from
+            );
+            return Err(BlockError::InvalidTransaction);
+        }
+        if self.confirmed_txs.contains(&tx.hash()) || self.mempool.contains(&tx) {
+            warn!("rejected transaction {} which is already known", tx.hash());
+            return Err(BlockError::ReplayedTransaction);
+        }
+        self.mempool.push(tx);
+        Ok(())
+    }
+
+    // mines a block with as many mempool transactions as fit, highest fee first and oldest first
+    // among equal fees, stopping at `max_txs_per_block` transactions or once the next one would
+    // push it over `max_block_bytes`
+    // a transaction its sender can't afford on top of the ones before it is skipped and stays
+    // in the mempool, it may become affordable later, one already confirmed is dropped
+    // the included transactions only leave the mempool once the block has been added
+    pub fn mine_pending(&mut self) -> Result<(), BlockchainError> {
+        if self.blocks.is_empty() {
+            return Err(BlockError::MissingGenesis.into());
+        }
+        let confirmed = &self.confirmed_txs;
+        self.mempool.retain(|tx| !confirmed.contains(&tx.hash()));
+        let mut by_fee = self.mempool.clone();
+        by_fee.sort_by_key(|tx| Reverse(tx.fee));
+        let mut included = vec![];
+        for tx in by_fee {
+            if included.len() == self.max_txs_per_block {
+                break;
+            }
+            included.push(tx);
+            if spend_transactions(&included, &self.balances).is_err() {
+                included.pop();
+            } else if self.max_block_len(&included) > self.max_block_bytes {
+                included.pop();
+                break;
+            }
+        }
+        let block = self.next_block_with_transactions(String::new(), included.clone())?;
+        self.try_add_block(block)?;
+        self.mempool.retain(|tx| !included.contains(tx));
+        Ok(())
+    }
+
+    // mines and adds one block per entry of `datas`, in order, returning the ids of the new blocks
+    // stops at the first block that can't be added, the blocks before it stay on the chain
+    pub fn mine_blocks(&mut self, datas: Vec<String>) -> Result<Vec<u64>, BlockchainError> {
+        if self.blocks.is_empty() {
+            return Err(BlockError::MissingGenesis.into());
+        }
+        let mut ids = Vec::with_capacity(datas.len());
+        for data in datas {
+            let block = self.try_next_block(data)?;
+            let id = block.id;
+            self.try_add_block(block)?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    // upper bound on the size of the next block if it carried `transactions` and no data,
+    // worked out before mining by using the longest nonce there is and a placeholder hash
+    fn max_block_len(&self, transactions: &[Transaction]) -> usize {
+        let tip = self.blocks.tip().expect("there is atleast a single block");
+        let id = tip.id + 1;
+        let mut template = Block::builder()
+            .id(id)
+            .previous_hash(tip.hash.clone())
+            .transactions(transactions.to_vec())
+            .network_id(self.network_id)
+            .nonce(u64::MAX)
+            .hash("0".repeat(64))
+            .build();
+        template.miner = self.miner_address.clone();
+        if self.consensus == ConsensusMode::ProofOfStake {
+            template.validator = self.select_validator(&tip.hash, id);
+        }
+        template.encoded_len()
+    }
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// implementation of mining scheme
+// when new block created, mine_block is called, which returns nonce & a hash
+
+impl<T: Serialize + Clone> Block<T> {
+    // mines a block carrying an arbitrary payload, hashed as its JSON encoding,
+    // for `String` that is the same hash `mine_block` finds
+    pub fn mine_payload(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &str,
+        payload: T,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        let result = MiningJob {
+            contents: BlockContents::new(id, timestamp, previous_hash, &payload),
+            difficulty,
+            algorithm,
+            prefix: None,
+        }
+        .search(0..=u64::MAX, || false)
+        .expect("nonce space exhausted");
+        Block {
+            id,
+            hash: result.hash,
+            previous_hash: previous_hash.to_string(),
+            timestamp,
+            data: payload,
+            nonce: result.nonce,
+            reward: 0,
+            transactions: vec![],
+            merkle_root: None,
+            validator: None,
+            miner: None,
+            network_id: DEFAULT_NETWORK_ID,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+        }
+    }
+
+    // checks a payload block the way `App` checks its own: it follows `previous`
+    // and passes `verify_self`
+    pub fn validate_payload(
+        &self,
+        previous: &Block<T>,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> Result<(), BlockError> {
+        if self.previous_hash != previous.hash {
+            return Err(BlockError::PreviousHashMismatch);
+        }
+        if previous.id.checked_add(1) != Some(self.id) {
+            return Err(BlockError::NonSequentialId);
+        }
+        self.verify_self(difficulty, algorithm)
+    }
+
+    // checks the block on its own, without its parent, e.g. a gossiped block before the parent
+    // has been fetched: the merkle root matches the transactions, the hash matches the contents
+    // and it meets the difficulty
+    pub fn verify_self(
+        &self,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> Result<(), BlockError> {
+        if self.merkle_root != expected_merkle_root(&self.transactions) {
+            return Err(BlockError::MerkleRootMismatch);
+        }
+        let hash = hex::decode(&self.hash).map_err(|_| BlockError::InvalidHashEncoding)?;
+        if hex::encode(calculate_hash(&self.contents(), self.nonce, algorithm)) != self.hash {
+            return Err(BlockError::HashMismatch);
+        }
+        if !meets_difficulty(&hash, difficulty) {
+            return Err(BlockError::InvalidDifficulty);
+        }
+        Ok(())
+    }
+
+    // the parts of this block that its hash commits to
+    fn contents(&self) -> BlockContents<'_> {
+        BlockContents {
+            id: self.id,
+            timestamp: self.timestamp,
+            previous_hash: &self.previous_hash,
+            data_json: serde_json::to_string(&self.data).expect("can serialize data"),
+            merkle_root: self.merkle_root.as_deref(),
+            validator: self.validator.as_deref(),
+            miner: self.miner.as_deref(),
+            network_id: self.network_id,
+            version: self.version,
+        }
+    }
+}
+
+impl Block {
+    // fails without mining when `data` is larger than DEFAULT_MAX_DATA_BYTES, empty data is always
+    // allowed, a block on its own knows nothing about the app it is for, `App::try_next_block`
+    // holds `data` to the app's `max_data_bytes` and `allow_empty_data` instead
+    pub fn new(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, BlockError> {
+        if data.len() > DEFAULT_MAX_DATA_BYTES {
+            warn!("block id: {}, has {} bytes of data", id, data.len());
+            return Err(BlockError::DataTooLarge);
+        }
+        Ok(Self::with_transactions(
+            id,
+            previous_hash,
+            data,
+            vec![],
+            difficulty,
+            algorithm,
+        ))
+    }
+
+    // mines a block with the default difficulty and hash function on tokio's blocking pool,
+    // so an async node keeps serving other tasks while the nonce is searched
+    #[cfg(feature = "tokio")]
+    pub async fn new_async(id: u64, previous_hash: String, data: String) -> Self {
+        tokio::task::spawn_blocking(move || {
+            Self::with_transactions(
+                id,
+                previous_hash,
+                data,
+                vec![],
+                DEFAULT_DIFFICULTY,
+                HashAlgorithm::default(),
+            )
+        })
+        .await
+        .expect("mining task panicked")
+    }
+
+    // mines a block which also commits to `transactions` through their merkle root
+    pub fn with_transactions(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        transactions: Vec<Transaction>,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        Self::mine_with_stats(id, previous_hash, data, transactions, difficulty, algorithm).0
+    }
+
+    // like `new`, but also returns how much work mining the block took
+    pub fn new_with_stats(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> (Self, MiningResult) {
+        Self::mine_with_stats(id, previous_hash, data, vec![], difficulty, algorithm)
+    }
+
+    fn mine_with_stats(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        transactions: Vec<Transaction>,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+    ) -> (Self, MiningResult) {
+        let now = Utc::now();
+        let merkle_root = expected_merkle_root(&transactions);
+        let job = MiningJob {
+            contents: BlockContents {
+                merkle_root: merkle_root.as_deref(),
+                ..BlockContents::new(id, now.timestamp(), &previous_hash, &data)
+            },
+            difficulty,
+            algorithm,
+            prefix: None,
+        };
+        let result = job
+            .search(0..=u64::MAX, || false)
+            .expect("nonce space exhausted");
+
+        let block = Self {
+            id,
+            hash: result.hash.clone(),
+            timestamp: now.timestamp(),
+            previous_hash,
+            data,
+            nonce: result.nonce,
+            reward: App::expected_reward(id, &transactions),
+            transactions,
+            merkle_root,
+            validator: None,
+            miner: None,
+            network_id: DEFAULT_NETWORK_ID,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+        };
+        (block, result)
+    }
+
+    // creates a proof of stake block produced by `validator`, there is no nonce to grind
+    // `transactions` are committed to through their merkle root like in `with_transactions`
+    pub fn new_staked(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        transactions: Vec<Transaction>,
+        validator: String,
+        network_id: u32,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        let now = Utc::now();
+        let merkle_root = expected_merkle_root(&transactions);
+        let contents = BlockContents {
+            merkle_root: merkle_root.as_deref(),
+            validator: Some(&validator),
+            network_id,
+            ..BlockContents::new(id, now.timestamp(), &previous_hash, &data)
+        };
+        let hash = hex::encode(calculate_hash(&contents, 0, algorithm));
+
+        Self {
+            id,
+            hash,
+            timestamp: now.timestamp(),
+            previous_hash,
+            data,
+            nonce: 0,
+            reward: App::expected_reward(id, &transactions),
+            transactions,
+            merkle_root,
+            validator: Some(validator),
+            miner: None,
+            network_id,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+        }
+    }
+
+    pub fn builder() -> BlockBuilder {
+        BlockBuilder::new()
+    }
+
+    // signs the block hash, so the block can be attributed to the holder of `secret_key`
+    // the signature isn't part of the hash, sign after mining and after any change to the block
+    pub fn sign(&mut self, secret_key: &SigningKey) {
+        self.miner_pubkey = Some(address(&secret_key.verifying_key()));
+        self.signature = Some(sign_message(self.hash.as_bytes(), secret_key));
+    }
+
+    // unsigned blocks are valid, signed ones need a signature over the hash by `miner_pubkey`
+    pub fn has_valid_signature(&self) -> bool {
+        match (&self.miner_pubkey, &self.signature) {
+            (None, None) => true,
+            (Some(pubkey), Some(signature)) => {
+                verify_message(pubkey, self.hash.as_bytes(), signature)
+            }
+            _ => false,
+        }
+    }
+
+    // single block in the JSON format chains are stored in, e.g. for gossiping it to peers
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("can serialize block")
+    }
+
+    // parses a block from `to_json`, it still has to be validated before it is trusted
+    pub fn from_json(json: &str) -> Result<Block, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    // size of the block in its JSON encoding, checked against `App::max_block_bytes`
+    pub fn encoded_len(&self) -> usize {
+        serde_json::to_vec(self).expect("can serialize block").len()
+    }
+
+    // number of zero bits the hash starts with, 0 if it isn't valid hex
+    pub fn hash_zero_bits(&self) -> u32 {
+        hex::decode(&self.hash)
+            .map(|hash| leading_zero_bits(&hash))
+            .unwrap_or(0)
+    }
+
+    // sanity checks on fields that came from outside, e.g. a deserialized block
+    // an id of u64::MAX could never be followed by another block, and hashes can't be empty
+    // a block of an unknown version is rejected before any of its fields are trusted
+    pub fn validate_fields(&self) -> Result<(), BlockError> {
+        if self.version != BLOCK_VERSION {
+            warn!(
+                "block id: {}, has unsupported version {}",
+                self.id, self.version
+            );
+            return Err(BlockError::UnsupportedVersion);
+        }
+        if self.id == u64::MAX || self.hash.is_empty() || self.previous_hash.is_empty() {
+            warn!("block id: {}, has malformed fields", self.id);
+            return Err(BlockError::MalformedFields);
+        }
+        Ok(())
+    }
+
+    // progress is reported to `on_progress` when given, e.g. to drive a progress bar,
+    // and logged otherwise, pass a closure that does nothing to silence it
+    pub fn mine_block(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &str,
+        data: &str,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+        on_progress: Option<&mut dyn FnMut(MiningProgress)>,
+    ) -> MiningResult {
+        let job = MiningJob {
+            contents: BlockContents::new(id, timestamp, previous_hash, data),
+            difficulty,
+            algorithm,
+            prefix: None,
+        };
+        match on_progress {
+            Some(on_progress) => job.search_with_progress(0..=u64::MAX, || false, on_progress),
+            None => job.search(0..=u64::MAX, || false),
+        }
+        .expect("nonce space exhausted")
+    }
+
+    // like mine_block, but gives up with None once `deadline` passes or `cancel` is set
+    // running out of nonces at u64::MAX also returns None instead of overflowing
+    #[allow(clippy::too_many_arguments)]
+    pub fn mine_block_with_deadline(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &str,
+        data: &str,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+        deadline: Instant,
+        cancel: &AtomicBool,
+    ) -> Option<(u64, String)> {
+        let job = MiningJob {
+            contents: BlockContents::new(id, timestamp, previous_hash, data),
+            difficulty,
+            algorithm,
+            prefix: None,
+        };
+        job.search(0..=u64::MAX, || {
+            cancel.load(Ordering::Relaxed) || Instant::now() >= deadline
+        })
+        .map(|result| (result.nonce, result.hash))
+    }
+
+    // like mine_block, but only tries the nonces in `start..end`, None if none of them works
+    // a coordinator can hand disjoint ranges to several machines mining the same block,
+    // an `end` not after `start` is an empty range
+    #[allow(clippy::too_many_arguments)]
+    pub fn mine_block_range(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &str,
+        data: &str,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+        start: u64,
+        end: u64,
+    ) -> Option<(u64, String)> {
+        let job = MiningJob {
+            contents: BlockContents::new(id, timestamp, previous_hash, data),
+            difficulty,
+            algorithm,
+            prefix: None,
+        };
+        job.search(start..end, || false)
+            .map(|result| (result.nonce, result.hash))
+    }
+
+    // splits the nonce space over `threads` threads, thread k tries k, k + threads, k + 2 * threads...
+    // the first thread to find a valid nonce tells the others to stop
+    pub fn mine_block_parallel(
+        id: u64,
+        timestamp: i64,
+        previous_hash: &str,
+        data: &str,
+        difficulty: Difficulty,
+        algorithm: HashAlgorithm,
+        threads: usize,
+    ) -> (u64, String) {
+        let threads = threads.max(1);
+        let job = MiningJob {
+            contents: BlockContents::new(id, timestamp, previous_hash, data),
+            difficulty,
+            algorithm,
+            prefix: None,
+        };
+        let found = AtomicBool::new(false);
+
+        let results: Vec<Option<MiningResult>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|k| {
+                    let job = &job;
+                    let found = &found;
+                    scope.spawn(move || {
+                        let result = job.search((k as u64..=u64::MAX).step_by(threads), || {
+                            found.load(Ordering::Relaxed)
+                        });
+                        if result.is_some() {
+                            found.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("mining thread panicked"))
+                .collect()
+        });
+
+        // more than one thread can finish before seeing the flag, prefer the lowest nonce
+        results
+            .into_iter()
+            .flatten()
+            .min_by_key(|result| result.nonce)
+            .map(|result| (result.nonce, result.hash))
+            .expect("nonce space exhausted")
+    }
+}
+
+// builds blocks field by field, mostly for tests which need fixed timestamps or nonces
+// only mines when no nonce was supplied, a supplied nonce without a hash gets its hash computed
+#[derive(Debug, Clone)]
+pub struct BlockBuilder {
+    id: u64,
+    previous_hash: String,
+    data: String,
+    timestamp: Option<i64>,
+    nonce: Option<u64>,
+    hash: Option<String>,
+    transactions: Vec<Transaction>,
+    miner: Option<String>,
+    network_id: u32,
+    difficulty: Difficulty,
+    algorithm: HashAlgorithm,
+    difficulty_prefix: Option<String>,
+}
+
+impl BlockBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: 0,
+            previous_hash: String::new(),
+            data: String::new(),
+            timestamp: None,
+            nonce: None,
+            hash: None,
+            transactions: vec![],
+            miner: None,
+            network_id: DEFAULT_NETWORK_ID,
+            difficulty: DEFAULT_DIFFICULTY,
+            algorithm: HashAlgorithm::default(),
+            difficulty_prefix: None,
+        }
+    }
+
+    pub fn id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn previous_hash(mut self, previous_hash: impl Into<String>) -> Self {
+        self.previous_hash = previous_hash.into();
+        self
+    }
+
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn hash(mut self, hash: impl Into<String>) -> Self {
+        self.hash = Some(hash.into());
+        self
+    }
+
+    pub fn transactions(mut self, transactions: Vec<Transaction>) -> Self {
+        self.transactions = transactions;
+        self
+    }
+
+    pub fn miner(mut self, miner: impl Into<String>) -> Self {
+        self.miner = Some(miner.into());
+        self
+    }
+
+    pub fn network_id(mut self, network_id: u32) -> Self {
+        self.network_id = network_id;
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    pub fn hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    // binary prefix the mined hash has to start with, replaces the difficulty
+    pub fn difficulty_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.difficulty_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn build(self) -> Block {
+        let timestamp = self.timestamp.unwrap_or_else(|| Utc::now().timestamp());
+        let merkle_root = expected_merkle_root(&self.transactions);
+        let contents = BlockContents {
+            merkle_root: merkle_root.as_deref(),
+            miner: self.miner.as_deref(),
+            network_id: self.network_id,
+            ..BlockContents::new(self.id, timestamp, &self.previous_hash, &self.data)
+        };
+        let (nonce, hash) = match (self.nonce, self.hash) {
+            (Some(nonce), Some(hash)) => (nonce, hash),
+            (Some(nonce), None) => (
+                nonce,
+                hex::encode(calculate_hash(&contents, nonce, self.algorithm)),
+            ),
+            (None, _) => MiningJob {
+                contents,
+                difficulty: self.difficulty,
+                algorithm: self.algorithm,
+                prefix: self.difficulty_prefix.as_deref(),
+            }
+            .search(0..=u64::MAX, || false)
+            .map(|result| (result.nonce, result.hash))
+            .expect("nonce space exhausted"),
+        };
+
+        Block {
+            id: self.id,
+            hash,
+            timestamp,
+            previous_hash: self.previous_hash,
+            data: self.data,
+            nonce,
+            reward: App::expected_reward(self.id, &self.transactions),
+            transactions: self.transactions,
+            merkle_root,
+            validator: None,
+            miner: self.miner,
+            network_id: self.network_id,
+            miner_pubkey: None,
+            signature: None,
+            version: BLOCK_VERSION,
+        }
+    }
+}
+
+impl Default for BlockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// outcome of a successful nonce search, with how much work it took
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MiningResult {
+    pub nonce: u64,
+    pub hash: String,
+    // number of hashes computed, for the serial miner this is nonce + 1
+    pub attempts: u64,
+    pub elapsed: Duration,
+}
+
+// periodic report from a running nonce search, see `Block::mine_block`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiningProgress {
+    // nonce about to be tried
+    pub nonce: u64,
+    pub attempts: u64,
+    pub elapsed: Duration,
+}
+
+// where progress goes when the caller doesn't ask for it
+fn log_progress(progress: MiningProgress) {
+    info!("nonce: {}", progress.nonce);
+}
+
+// the fixed inputs of a block while a nonce for it is being searched
+struct MiningJob<'a> {
+    contents: BlockContents<'a>,
+    difficulty: Difficulty,
+    algorithm: HashAlgorithm,
+    // legacy binary prefix the hash has to start with instead, see `App::set_difficulty_prefix`
+    prefix: Option<&'a str>,
+}
+
+impl MiningJob<'_> {
+    // tries the given nonces in order until one meets the difficulty or `should_stop` returns true
+    fn search(
+        &self,
+        nonces: impl Iterator<Item = u64>,
+        should_stop: impl FnMut() -> bool,
+    ) -> Option<MiningResult> {
+        self.search_with_progress(nonces, should_stop, log_progress)
+    }
+
+    // like `search`, reporting every LOG_INTERVAL-th nonce to `on_progress` instead of the log
+    fn search_with_progress(
+        &self,
+        nonces: impl Iterator<Item = u64>,
+        mut should_stop: impl FnMut() -> bool,
+        mut on_progress: impl FnMut(MiningProgress),
+    ) -> Option<MiningResult> {
+        info!("Mining block...");
+        let started = Instant::now();
+        // only the nonce changes between attempts, the prefix is absorbed once up front
+        let input = HashInput::new(&self.contents);
+        let hasher = PrefixHasher::new(&input.prefix, self.algorithm);
+
+        for (tried, nonce) in nonces.enumerate() {
+            if should_stop() {
+                info!("mining stopped at nonce: {}", nonce);
+                return None;
+            }
+            if should_log(nonce) {
+                on_progress(MiningProgress {
+                    nonce,
+                    attempts: tried as u64 + 1,
+                    elapsed: started.elapsed(),
+                });
+            }
+            let hash = hasher.finish(nonce, &input.suffix);
+            let meets = match self.prefix {
+                Some(prefix) => meets_prefix(&hash, prefix),
+                None => meets_difficulty(&hash, self.difficulty),
+            };
+            if meets {
+                info!(
+                    "block mined! nonce: {}, hash: {}, binary hash: {}",
+                    nonce,
+                    hex::encode(&hash),
+                    hash_to_binary_representation(&hash)
+                );
+                return Some(MiningResult {
+                    nonce,
+                    hash: hex::encode(&hash),
+                    attempts: tried as u64 + 1,
+                    elapsed: started.elapsed(),
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{address, sign_transaction};
+    use k256::ecdsa::SigningKey;
+    use std::sync::atomic::AtomicUsize;
+    use std::{env, path::PathBuf, time::Duration};
+
+    #[test]
+    fn mining_at_eight_bits_gives_eight_leading_zeros() {
+        let block = Block::new(
+            1,
+            "genesis".to_string(),
+            "data".to_string(),
+            Difficulty::from_bits(8),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let hash = hex::decode(&block.hash).unwrap();
+        assert!(hash_to_binary_representation(&hash).starts_with("00000000"));
+    }
+
+    #[test]
+    fn app_accepts_blocks_mined_at_its_difficulty() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(1));
+        app.genesis();
+        let latest = app.blocks.last().unwrap();
+        let block = Block::new(
+            latest.id + 1,
+            latest.hash.clone(),
+            "one".to_string(),
+            Difficulty::from_bits(1),
+            app.hash_algorithm,
+        )
+        .unwrap();
+
+        assert_eq!(
+            app.is_block_valid(
+                &block,
+                latest,
+                &app.balances,
+                &app.confirmed_txs,
+                app.next_difficulty()
+            ),
+            Ok(())
+        );
+    }
+
+    // file in the temp directory nothing else uses, removed by the test itself
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rust-blockchain-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn saved_chain_loads_back_equal() {
+        let mut app = App::init();
+        app.mine_blocks(vec![
+            "one".to_string(),
+            "two".to_string(),
+            "three".to_string(),
+        ])
+        .unwrap();
+        let path = temp_path("round-trip.json");
+
+        app.save_to_file(&path).unwrap();
+        let loaded = App::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.blocks, app.blocks);
+    }
+
+    #[test]
+    fn empty_and_malformed_files_fail_to_load() {
+        let path = temp_path("malformed.json");
+
+        fs::write(&path, "").unwrap();
+        let empty = App::load_from_file(&path);
+        fs::write(&path, "[{\"id\":").unwrap();
+        let malformed = App::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            empty,
+            Err(BlockchainError::InvalidChainAt {
+                index: 0,
+                source: BlockError::MissingGenesis
+            })
+        ));
+        assert!(matches!(malformed, Err(BlockchainError::Json(_))));
+    }
+
+    #[test]
+    fn binary_representation_pads_every_byte() {
+        assert_eq!(
+            hash_to_binary_representation(&[0x00, 0x0F, 0xFF]),
+            "000000000000111111111111"
+        );
+    }
+
+    #[test]
+    fn try_add_block_says_why_a_block_was_rejected() {
+        let mut app = App::new();
+        app.genesis();
+        let genesis_hash = app.blocks[0].hash.clone();
+        let block = Block::new(
+            5,
+            genesis_hash.clone(),
+            "skips ahead".to_string(),
+            app.difficulty,
+            app.hash_algorithm,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::NonSequentialId))
+        ));
+        assert_eq!(app.blocks.len(), 1);
+
+        let block = Block::new(
+            1,
+            genesis_hash,
+            "next".to_string(),
+            app.difficulty,
+            app.hash_algorithm,
+        )
+        .unwrap();
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn hash_algorithms_give_different_hashes_that_meet_the_difficulty() {
+        let mine = |algorithm| {
+            Block::new(
+                1,
+                "genesis".to_string(),
+                "same data".to_string(),
+                Difficulty::from_bits(6),
+                algorithm,
+            )
+            .unwrap()
+        };
+        let sha256 = mine(HashAlgorithm::Sha256);
+        let blake3 = mine(HashAlgorithm::Blake3);
+
+        assert_ne!(sha256.hash, blake3.hash);
+        for (block, algorithm) in [
+            (sha256, HashAlgorithm::Sha256),
+            (blake3, HashAlgorithm::Blake3),
+        ] {
+            let contents =
+                BlockContents::new(block.id, block.timestamp, &block.previous_hash, &block.data);
+            let hash = calculate_hash(&contents, block.nonce, algorithm);
+            assert_eq!(hex::encode(&hash), block.hash);
+            assert!(meets_difficulty(&hash, Difficulty::from_bits(6)));
+        }
+    }
+
+    #[test]
+    fn app_mines_and_validates_with_its_hash_algorithm() {
+        let mut app = App::with_hash_algorithm(HashAlgorithm::Sha3_256);
+        app.genesis();
+        let latest = app.blocks.last().unwrap();
+        let block = Block::new(
+            latest.id + 1,
+            latest.hash.clone(),
+            "sha3".to_string(),
+            app.difficulty,
+            app.hash_algorithm,
+        )
+        .unwrap();
+
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn mining_gives_up_at_the_deadline() {
+        let cancel = AtomicBool::new(false);
+        let mined = Block::mine_block_with_deadline(
+            1,
+            Utc::now().timestamp(),
+            "genesis",
+            "never found",
+            Difficulty::MAX,
+            HashAlgorithm::Sha256,
+            Instant::now() + Duration::from_millis(50),
+            &cancel,
+        );
+        assert_eq!(mined, None);
+    }
+
+    #[test]
+    fn mining_stops_when_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let mined = Block::mine_block_with_deadline(
+            1,
+            Utc::now().timestamp(),
+            "genesis",
+            "never found",
+            Difficulty::MAX,
+            HashAlgorithm::Sha256,
+            Instant::now() + Duration::from_secs(60),
+            &cancel,
+        );
+        assert_eq!(mined, None);
+    }
+
+    #[test]
+    fn mining_before_the_deadline_finds_a_valid_nonce() {
+        let cancel = AtomicBool::new(false);
+        let timestamp = Utc::now().timestamp();
+        let (nonce, hash) = Block::mine_block_with_deadline(
+            1,
+            timestamp,
+            "genesis",
+            "easy",
+            Difficulty::from_bits(4),
+            HashAlgorithm::Sha256,
+            Instant::now() + Duration::from_secs(60),
+            &cancel,
+        )
+        .unwrap();
+
+        let expected = calculate_hash(
+            &BlockContents::new(1, timestamp, "genesis", "easy"),
+            nonce,
+            HashAlgorithm::Sha256,
+        );
+        assert_eq!(hex::encode(&expected), hash);
+        assert!(meets_difficulty(&expected, Difficulty::from_bits(4)));
+    }
+
+    #[test]
+    fn running_out_of_nonces_returns_none() {
+        let job = MiningJob {
+            contents: BlockContents::new(1, GENESIS_TIMESTAMP, "genesis", "never found"),
+            difficulty: Difficulty::MAX,
+            algorithm: HashAlgorithm::Sha256,
+            prefix: None,
+        };
+        assert_eq!(job.search(u64::MAX - 2..=u64::MAX, || false), None);
+    }
+
+    #[test]
+    fn parallel_mining_finds_blocks_the_chain_accepts() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(8));
+        app.genesis();
+        let genesis = app.blocks[0].clone();
+        let timestamp = Utc::now().timestamp();
+
+        for _ in 0..2 {
+            let (nonce, hash) = Block::mine_block_parallel(
+                1,
+                timestamp,
+                &genesis.hash,
+                "parallel",
+                app.difficulty,
+                HashAlgorithm::Sha256,
+                4,
+            );
+            let block = Block::builder()
+                .id(1)
+                .previous_hash(genesis.hash.clone())
+                .data("parallel")
+                .timestamp(timestamp)
+                .nonce(nonce)
+                .build();
+            assert_eq!(block.hash, hash);
+            assert_eq!(
+                app.is_block_valid(
+                    &block,
+                    &genesis,
+                    &app.balances,
+                    &app.confirmed_txs,
+                    app.next_difficulty()
+                ),
+                Ok(())
+            );
+        }
+    }
+
+    // app on a zero difficulty chain holding just the genesis block
+    fn test_app() -> App {
+        let mut app = App::with_difficulty(Difficulty::ZERO);
+        app.genesis();
+        app
+    }
+
+    // appends one block per entry of `datas` to `chain`, ten seconds apart
+    // with zero difficulty nothing is mined, so the same `datas` always give the same blocks
+    fn extended(chain: &[Block], datas: &[&str]) -> Vec<Block> {
+        let mut app = App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(chain.to_vec())
+            .unwrap();
+        app.mine_blocks(datas.iter().map(|data| data.to_string()).collect())
+            .unwrap();
+        app.blocks
+    }
+
+    // chain of the genesis block and one block per entry of `datas` after it
+    fn test_blocks(datas: &[&str]) -> Vec<Block> {
+        let mut app = test_app();
+        app.mine_blocks(datas.iter().map(|data| data.to_string()).collect())
+            .unwrap();
+        app.blocks
+    }
+
+    fn tampered(mut chain: Vec<Block>) -> Vec<Block> {
+        chain[1].data = "tampered".to_string();
+        chain
+    }
+
+    fn hashes(chain: &[Block]) -> Vec<String> {
+        chain.iter().map(|b| b.hash.clone()).collect()
+    }
+
+    #[test]
+    fn choose_chain_prefers_the_longer_valid_chain() {
+        let local = test_blocks(&["a"]);
+        let mut remote = local.clone();
+        remote.extend(
+            App::with_difficulty(Difficulty::ZERO)
+                .with_blocks(local.clone())
+                .map(|mut app| {
+                    app.mine_blocks(vec!["b".to_string()]).unwrap();
+                    app.blocks.split_off(local.len())
+                })
+                .unwrap(),
+        );
+
+        assert_eq!(
+            test_app().choose_chain(local, remote.clone()).unwrap(),
+            remote
+        );
+    }
+
+    #[test]
+    fn choose_chain_keeps_the_valid_local_chain() {
+        let local = test_blocks(&["a"]);
+        let remote = tampered(test_blocks(&["b", "c"]));
+
+        let chosen = test_app().choose_chain(local.clone(), remote).unwrap();
+        assert_eq!(hashes(&chosen), hashes(&local));
+    }
+
+    #[test]
+    fn choose_chain_takes_a_valid_remote_over_an_invalid_local_chain() {
+        let local = tampered(test_blocks(&["a"]));
+        let remote = test_blocks(&["b"]);
+
+        let chosen = test_app().choose_chain(local, remote.clone()).unwrap();
+        assert_eq!(hashes(&chosen), hashes(&remote));
+    }
+
+    #[test]
+    fn choose_chain_fails_when_both_chains_are_invalid() {
+        let local = tampered(test_blocks(&["a"]));
+        let remote = tampered(test_blocks(&["b"]));
+        assert!(matches!(
+            test_app().choose_chain(local, remote),
+            Err(BlockchainError::Chain(ChainError::BothInvalid))
+        ));
+    }
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32]).unwrap()
+    }
+
+    fn address_of(key: &SigningKey) -> String {
+        address(&key.verifying_key())
+    }
+
+    fn transfer(from: &SigningKey, to: &str, amount: u64) -> Transaction {
+        let mut tx = Transaction::new(address_of(from), to.to_string(), amount);
+        sign_transaction(&mut tx, from);
+        tx
+    }
+
+    fn transfer_with_fee(from: &SigningKey, to: &str, amount: u64, fee: u64) -> Transaction {
+        let mut tx = Transaction::new(address_of(from), to.to_string(), amount).with_fee(fee);
+        sign_transaction(&mut tx, from);
+        tx
+    }
+
+    // zero difficulty block after the tip of `app` carrying `transactions`
+    fn block_with(app: &App, transactions: Vec<Transaction>) -> Block {
+        let latest = app.blocks.last().unwrap();
+        Block::with_transactions(
+            latest.id + 1,
+            latest.hash.clone(),
+            "payments".to_string(),
+            transactions,
+            Difficulty::ZERO,
+            HashAlgorithm::Sha256,
+        )
+    }
+
+    #[test]
+    fn swapped_transaction_breaks_the_merkle_root() {
+        let alice = key(1);
+        let app = funded_app(&alice, 100);
+        let mut block = block_with(
+            &app,
+            vec![transfer(&alice, "bob", 10), transfer(&alice, "carol", 20)],
+        );
+        assert_eq!(check_on_tip(&app, &block), Ok(()));
+
+        // validly signed, so only the merkle root gives it away
+        block.transactions[1] = transfer(&alice, "carol", 25);
+        assert_eq!(
+            check_on_tip(&app, &block),
+            Err(BlockError::MerkleRootMismatch)
+        );
+    }
+
+    #[test]
+    fn chain_of_just_genesis_is_valid() {
+        let mut app = App::init();
+        let genesis = app.blocks[0].clone();
+
+        assert_eq!(
+            hex::encode(calculate_hash(
+                &genesis.contents(),
+                genesis.nonce,
+                HashAlgorithm::Sha256
+            )),
+            genesis.hash
+        );
+        assert!(app.is_chain_valid(&[genesis]));
+    }
+
+    #[test]
+    fn rapid_blocks_raise_the_difficulty_monotonically() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(2));
+        app.genesis();
+        app.seed_timestamp = Some(GENESIS_TIMESTAMP + 100);
+
+        let mut difficulties = vec![app.next_difficulty()];
+        for i in 0..5 {
+            app.mine_blocks(vec![format!("block {}", i)]).unwrap();
+            difficulties.push(app.next_difficulty());
+        }
+
+        assert!(difficulties.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(difficulties.last() > difficulties.first());
+    }
+
+    #[test]
+    fn slow_blocks_lower_the_difficulty() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(6));
+        app.genesis();
+
+        let mut difficulties = vec![app.next_difficulty()];
+        for i in 1..=5 {
+            app.seed_timestamp = Some(GENESIS_TIMESTAMP + i * 100);
+            app.mine_blocks(vec![format!("block {}", i)]).unwrap();
+            difficulties.push(app.next_difficulty());
+        }
+
+        assert!(difficulties.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(difficulties.last() < difficulties.first());
+        app.min_difficulty = Difficulty::from_bits(5);
+        assert_eq!(app.next_difficulty(), app.min_difficulty);
+    }
+
+    #[test]
+    fn get_finds_blocks_by_id() {
+        let mut app = test_app();
+        app.blocks = test_blocks(&["a", "b"]);
+
+        assert_eq!(
+            app.get(2).map(|b| &b.hash),
+            app.blocks.last().map(|b| &b.hash)
+        );
+        assert!(app.get(999).is_none());
+        assert_eq!(app.iter().count(), 3);
+        assert_eq!(app.len(), 3);
+    }
+
+    #[test]
+    fn mined_chain_is_valid_and_a_swapped_one_is_not() {
+        let mut app = test_app();
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+        let mut chain = app.blocks.clone();
+        assert!(app.is_chain_valid(&chain));
+
+        chain.swap(1, 2);
+        assert!(!app.is_chain_valid(&chain));
+    }
+
+    #[test]
+    fn reward_halves_at_the_interval() {
+        assert_eq!(App::block_reward(HALVING_INTERVAL - 1), BASE_REWARD);
+        assert_eq!(App::block_reward(HALVING_INTERVAL), BASE_REWARD / 2);
+        assert_eq!(App::block_reward(HALVING_INTERVAL + 1), BASE_REWARD / 2);
+        assert_eq!(App::block_reward(HALVING_INTERVAL * 64), 0);
+    }
+
+    #[test]
+    fn confirmed_transactions_can_not_be_replayed() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        let height = app.len();
+        let tx = transfer(&alice, "bob", 10);
+
+        let twice = block_with(&app, vec![tx.clone(), tx.clone()]);
+        assert!(matches!(
+            app.try_add_block(twice),
+            Err(BlockchainError::Block(BlockError::ReplayedTransaction))
+        ));
+        app.try_add_block(block_with(&app, vec![tx.clone()]))
+            .unwrap();
+
+        let replay = block_with(&app, vec![tx]);
+        assert!(matches!(
+            app.try_add_block(replay),
+            Err(BlockchainError::Block(BlockError::ReplayedTransaction))
+        ));
+        assert_eq!(app.len(), height + 1);
+    }
+
+    #[test]
+    fn shorter_chain_with_more_work_wins() {
+        let longer = test_blocks(&["a", "b", "c"]);
+        let mut heavier = test_app().blocks;
+        let block = Block::new(
+            1,
+            heavier[0].hash.clone(),
+            "heavy".to_string(),
+            Difficulty::from_bits(12),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        heavier.push(block);
+        assert!(App::total_work(&heavier) > App::total_work(&longer));
+
+        let chosen = test_app().choose_chain(longer, heavier.clone()).unwrap();
+        assert_eq!(hashes(&chosen), hashes(&heavier));
+    }
+
+    #[test]
+    fn find_invalid_block_reports_where_the_chain_breaks() {
+        let chain = test_blocks(&["one", "two", "three", "four"]);
+        let app = test_app();
+        assert_eq!(app.find_invalid_block(&chain), None);
+
+        for index in [0, 2, chain.len() - 1] {
+            let mut broken = chain.clone();
+            broken[index].data = "corrupted".to_string();
+            assert_eq!(app.find_invalid_block(&broken), Some(index));
+        }
+    }
+
+    #[test]
+    fn proof_of_work_chain_is_valid() {
+        let chain = test_blocks(&["one", "two", "three"]);
+        assert_eq!(test_app().find_invalid_block(&chain), None);
+    }
+
+    #[test]
+    fn proof_of_stake_chain_is_valid() {
+        let stakes = BTreeMap::from([("alice".to_string(), 10), ("bob".to_string(), 30)]);
+        let mut app = App::with_proof_of_stake(stakes.clone());
+        app.genesis();
+        app.mine_blocks(vec![
+            "one".to_string(),
+            "two".to_string(),
+            "three".to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(app.find_invalid_block(&app.blocks), None);
+        for block in &app.blocks[1..] {
+            let validator = block.validator.as_ref().unwrap();
+            assert!(stakes.contains_key(validator));
+        }
+    }
+
+    #[test]
+    fn mempool_drains_over_several_blocks() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        app.max_txs_per_block = 3;
+        for amount in 1..=5 {
+            app.add_to_mempool(transfer(&alice, "bob", amount)).unwrap();
+        }
+
+        app.mine_pending().unwrap();
+        assert_eq!(app.blocks.last().unwrap().transactions.len(), 3);
+        assert_eq!(app.mempool.len(), 2);
+
+        app.mine_pending().unwrap();
+        assert_eq!(app.blocks.last().unwrap().transactions.len(), 2);
+        assert!(app.mempool.is_empty());
+        assert_eq!(app.balance_of("bob"), 15);
+    }
+
+    #[test]
+    fn mempool_rejects_unsigned_transactions() {
+        let mut app = test_app();
+        let mut tx = transfer(&key(1), "bob", 10);
+        tx.amount = 20;
+
+        assert_eq!(app.add_to_mempool(tx), Err(BlockError::InvalidTransaction));
+        assert!(app.mempool.is_empty());
+    }
+
+    #[test]
+    fn mine_pending_drops_transactions_confirmed_in_the_meantime() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        let tx = transfer(&alice, "bob", 10);
+        app.add_to_mempool(tx.clone()).unwrap();
+        app.try_add_block(block_with(&app, vec![tx])).unwrap();
+        let height = app.blocks.len();
+
+        app.mine_pending().unwrap();
+        assert_eq!(app.blocks.len(), height + 1);
+        assert!(app.blocks.last().unwrap().transactions.is_empty());
+        assert!(app.mempool.is_empty());
+    }
+
+    #[test]
+    fn crafted_json_is_rejected_gracefully() {
+        let block = |id: &str, nonce: &str| {
+            format!(
+                r#"{{"id":{},"hash":"00","previous_hash":"00","timestamp":0,"data":"","nonce":{}}}"#,
+                id, nonce
+            )
+        };
+        assert!(serde_json::from_str::<Block>(&block("-1", "0")).is_err());
+        assert!(serde_json::from_str::<Block>(&block("0", "18446744073709551616")).is_err());
+
+        let max_id: Block = serde_json::from_str(&block("18446744073709551615", "0")).unwrap();
+        assert_eq!(max_id.validate_fields(), Err(BlockError::MalformedFields));
+
+        let mut app = App::init();
+        assert!(matches!(
+            app.try_add_block(max_id),
+            Err(BlockchainError::Block(BlockError::MalformedFields))
+        ));
+    }
+
+    #[test]
+    fn built_block_hash_is_reproducible() {
+        let build = || {
+            Block::builder()
+                .id(1)
+                .previous_hash("genesis")
+                .data("fixed")
+                .timestamp(GENESIS_TIMESTAMP)
+                .nonce(7)
+                .build()
+        };
+        assert_eq!(build().hash, build().hash);
+        assert_eq!(
+            build().hash,
+            "0dcc2cfc61bdac612525d6673b6790f989206c6b6a7f32cabd87adbeb0d27767"
+        );
+    }
+
+    #[test]
+    fn chain_hash_changes_when_a_block_is_changed() {
+        let mut app = test_app();
+        app.blocks = test_blocks(&["a", "b", "c"]);
+        let fingerprint = app.chain_hash();
+        let mut same = test_app();
+        same.blocks = test_blocks(&["a", "b", "c"]);
+        assert_eq!(same.chain_hash(), fingerprint);
+
+        app.blocks[2].data = "changed".to_string();
+        assert_ne!(app.chain_hash(), fingerprint);
+    }
+
+    // zero difficulty app whose genesis block gives `amount` coins to the owner of `key`
+    fn funded_app(key: &SigningKey, amount: u64) -> App {
+        let mut app = App::with_difficulty(Difficulty::ZERO);
+        app.genesis_with_allocations(HashMap::from([(address_of(key), amount)]))
+            .unwrap();
+        app
+    }
+
+    // block after `previous`, ten seconds later and without proof of work
+    fn child_builder(previous: &Block) -> BlockBuilder {
+        Block::builder()
+            .id(previous.id + 1)
+            .previous_hash(previous.hash.clone())
+            .timestamp(previous.timestamp + 10)
+            .difficulty(Difficulty::ZERO)
+    }
+
+    #[test]
+    fn orphan_is_connected_once_its_parent_arrives() {
+        let mut app = test_app();
+        let first = child_builder(&app.blocks[0]).data("first").build();
+        let second = child_builder(&first).data("second").build();
+
+        assert!(matches!(
+            app.try_add_block(second.clone()),
+            Err(BlockchainError::Block(BlockError::Orphan))
+        ));
+        app.try_add_block(first.clone()).unwrap();
+
+        assert_eq!(hashes(&app.blocks[1..]), hashes(&[first, second]));
+        assert!(app.orphan_pool.is_empty());
+    }
+
+    #[test]
+    fn orphans_with_a_wrong_hash_are_not_buffered() {
+        let mut app = test_app();
+        let first = child_builder(&app.blocks[0]).data("first").build();
+        let mut second = child_builder(&first).data("second").build();
+        second.nonce += 1;
+
+        assert!(matches!(
+            app.try_add_block(second),
+            Err(BlockchainError::Block(BlockError::HashMismatch))
+        ));
+        assert!(app.orphan_pool.is_empty());
+    }
+
+    #[test]
+    fn orphan_pool_is_capped() {
+        let mut app = test_app();
+        app.max_orphans = 1;
+        let orphan = |data: &str| {
+            Block::builder()
+                .id(5)
+                .previous_hash("ab".repeat(32))
+                .data(data)
+                .timestamp(GENESIS_TIMESTAMP + 50)
+                .difficulty(Difficulty::ZERO)
+                .build()
+        };
+
+        assert!(matches!(
+            app.try_add_block(orphan("kept")),
+            Err(BlockchainError::Block(BlockError::Orphan))
+        ));
+        assert!(matches!(
+            app.try_add_block(orphan("dropped")),
+            Err(BlockchainError::Block(BlockError::OrphanPoolFull))
+        ));
+        assert_eq!(app.orphan_pool.values().map(Vec::len).sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn mining_stats_count_every_attempt() {
+        let (block, stats) = Block::new_with_stats(
+            1,
+            "genesis".to_string(),
+            "stats".to_string(),
+            Difficulty::from_bits(8),
+            HashAlgorithm::Sha256,
+        );
+
+        assert_eq!(stats.attempts, stats.nonce + 1);
+        assert_eq!((stats.nonce, stats.hash), (block.nonce, block.hash));
+        assert!(stats.elapsed >= Duration::ZERO);
+    }
+
+    #[test]
+    fn balances_follow_the_transfers_in_mined_blocks() {
+        let alice = key(1);
+        let mut app = test_app();
+        app.miner_address = Some(address_of(&alice));
+        app.mine_pending().unwrap();
+        app.miner_address = Some("carol".to_string());
+
+        app.add_to_mempool(transfer(&alice, "bob", 30)).unwrap();
+        app.mine_pending().unwrap();
+        app.add_to_mempool(transfer(&alice, "bob", 20)).unwrap();
+        app.mine_pending().unwrap();
+
+        assert_eq!(app.balance_of(&address_of(&alice)), 0);
+        assert_eq!(app.balance_of("bob"), 50);
+        assert_eq!(app.balance_of("carol"), 100);
+    }
+
+    #[test]
+    fn progress_is_logged_every_interval() {
+        assert!(should_log(0));
+        assert!(should_log(100_000));
+        assert!(should_log(200_000));
+        assert!(!should_log(50_000));
+    }
+
+    // 32 byte hash starting with `start`, every bit after it set
+    fn hash_starting_with(start: &[u8]) -> [u8; 32] {
+        let mut hash = [0xff; 32];
+        hash[..start.len()].copy_from_slice(start);
+        hash
+    }
+
+    #[test]
+    fn difficulty_counts_leading_zero_bits() {
+        let cases: [(u32, &[u8], &[u8]); 4] = [
+            (1, &[0x7f], &[0x80]),
+            (7, &[0x01], &[0x02]),
+            (8, &[0x00], &[0x01]),
+            (9, &[0x00, 0x7f], &[0x00, 0x80]),
+        ];
+        for (bits, meets, misses) in cases {
+            let difficulty = Difficulty::from_bits(bits);
+            assert!(meets_difficulty(&hash_starting_with(meets), difficulty));
+            assert!(!meets_difficulty(&hash_starting_with(misses), difficulty));
+        }
+    }
+
+    #[test]
+    fn pruned_chain_still_validates() {
+        let mut app = test_app();
+        app.mine_blocks((1..20).map(|i| format!("block {}", i)).collect())
+            .unwrap();
+        let tip = app.blocks.last().cloned();
+
+        app.prune(5);
+
+        assert_eq!(app.blocks.len(), 5);
+        assert_eq!(app.blocks.last().cloned(), tip);
+        assert_eq!(app.pruned_tip.as_ref().map(|block| block.id), Some(14));
+        assert!(app.pruned_state_root.is_some());
+        assert_eq!(app.find_invalid_block(&app.blocks), None);
+        assert!(app.is_chain_valid(&app.blocks.clone()));
+        app.mine_blocks(vec!["after pruning".to_string()]).unwrap();
+    }
+
+    #[test]
+    fn transactions_from_pruned_blocks_can_not_be_replayed() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 1000);
+        let tx = transfer(&alice, "bob", 100);
+        app.add_to_mempool(tx.clone()).unwrap();
+        app.mine_pending().unwrap();
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+        app.prune(1);
+
+        let replay = child_builder(app.blocks.last().unwrap())
+            .transactions(vec![tx])
+            .build();
+        let mut chain = app.blocks.clone();
+        chain.push(replay.clone());
+        assert!(matches!(
+            app.reorg_to(chain),
+            Err(BlockchainError::Chain(ChainError::InvalidChain))
+        ));
+        assert!(matches!(
+            app.try_add_block(replay),
+            Err(BlockchainError::Block(BlockError::ReplayedTransaction))
+        ));
+        assert_eq!(app.balance_of("bob"), 100);
+    }
+
+    #[test]
+    fn blocks_are_found_by_hash() {
+        let mut app = test_app();
+        for data in ["one", "two"] {
+            let block = app.next_block(data.to_string());
+            app.try_add_block(block).unwrap();
+        }
+        let block = &app.blocks[1];
+
+        let found = app.get_block_by_hash(&block.hash);
+        assert_eq!(found.map(|found| found.id), Some(block.id));
+        assert!(app.get_block_by_hash(&"00".repeat(32)).is_none());
+        assert_eq!(app.get_chain_info().height, 2);
+    }
+
+    fn check_on_tip(app: &App, block: &Block) -> Result<(), BlockError> {
+        app.is_block_valid(
+            block,
+            app.blocks.last().unwrap(),
+            &app.balances,
+            &app.confirmed_txs,
+            app.next_difficulty(),
+        )
+    }
+
+    #[test]
+    fn non_hex_hash_is_an_error() {
+        let app = test_app();
+        let block = child_builder(&app.blocks[0])
+            .data("not hex")
+            .nonce(0)
+            .hash("not a hex hash")
+            .build();
+        assert_eq!(
+            app.is_block_valid(
+                &block,
+                &app.blocks[0],
+                &app.balances,
+                &app.confirmed_txs,
+                app.next_difficulty()
+            ),
+            Err(BlockError::InvalidHashEncoding)
+        );
+    }
+
+    #[test]
+    fn block_after_the_largest_id_is_rejected() {
+        let app = test_app();
+        let previous = Block::builder()
+            .id(u64::MAX)
+            .previous_hash(app.blocks[0].hash.clone())
+            .data("last id")
+            .timestamp(GENESIS_TIMESTAMP + 10)
+            .difficulty(Difficulty::ZERO)
+            .build();
+        let block = child_builder(&app.blocks[0])
+            .id(0)
+            .previous_hash(previous.hash.clone())
+            .timestamp(previous.timestamp + 10)
+            .data("wraps around")
+            .build();
+
+        assert_eq!(
+            app.is_block_valid(
+                &block,
+                &previous,
+                &app.balances,
+                &app.confirmed_txs,
+                app.next_difficulty()
+            ),
+            Err(BlockError::NonSequentialId)
+        );
+    }
+
+    #[test]
+    fn test_chain_is_byte_identical_every_time() {
+        let first = App::test_chain(5);
+        let second = App::test_chain(5);
+
+        assert_eq!(first.blocks.len(), 5);
+        assert_eq!(
+            serde_json::to_vec(&first.blocks).unwrap(),
+            serde_json::to_vec(&second.blocks).unwrap()
+        );
+    }
+
+    #[test]
+    fn csv_export_has_a_row_per_block() {
+        let mut app = test_app();
+        app.mine_blocks(vec!["plain".to_string(), "with, a comma".to_string()])
+            .unwrap();
+        let mut csv = Vec::new();
+        app.export_csv(&mut csv).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("id,"));
+        assert_eq!(lines.count(), app.blocks.len());
+    }
+
+    #[test]
+    fn timestamps_before_the_parent_are_rejected() {
+        let app = test_app();
+        let block = child_builder(&app.blocks[0])
+            .data("backwards")
+            .timestamp(GENESIS_TIMESTAMP - 1)
+            .build();
+        assert_eq!(
+            check_on_tip(&app, &block),
+            Err(BlockError::TimestampBeforeParent)
+        );
+    }
+
+    #[test]
+    fn timestamps_far_in_the_future_are_rejected() {
+        let app = test_app();
+        let block = child_builder(&app.blocks[0])
+            .data("from the future")
+            .timestamp(Utc::now().timestamp() + 3 * 60 * 60)
+            .build();
+        assert_eq!(
+            check_on_tip(&app, &block),
+            Err(BlockError::TimestampInFuture)
+        );
+    }
+
+    #[test]
+    fn reorg_onto_an_extension_rolls_nothing_back() {
+        let mut app = test_app();
+        app.mine_blocks(vec!["a".to_string()]).unwrap();
+        let longer = extended(&app.blocks, &["b", "c"]);
+
+        let report = app.reorg_to(longer.clone()).unwrap();
+
+        assert_eq!(
+            report,
+            ReorgReport {
+                common_ancestor: Some(1),
+                rolled_back: 0,
+                added: 2,
+            }
+        );
+        assert_eq!(app.blocks, longer);
+    }
+
+    #[test]
+    fn reorg_onto_a_fork_rolls_back_to_the_common_ancestor() {
+        let mut app = test_app();
+        let genesis = app.blocks.clone();
+        app.mine_blocks(vec!["a1".to_string(), "a2".to_string()])
+            .unwrap();
+        let fork = extended(&genesis, &["b1", "b2", "b3"]);
+
+        let report = app.reorg_to(fork.clone()).unwrap();
+
+        assert_eq!(
+            report,
+            ReorgReport {
+                common_ancestor: Some(0),
+                rolled_back: 2,
+                added: 3,
+            }
+        );
+        assert_eq!(app.blocks, fork);
+    }
+
+    #[test]
+    fn reorg_refuses_an_empty_chain_or_another_genesis() {
+        let mut app = test_app();
+        app.mine_blocks(vec!["a".to_string()]).unwrap();
+        let before = app.blocks.clone();
+        let mut other = App::with_difficulty(Difficulty::ZERO);
+        other
+            .genesis_with("other network".to_string(), GENESIS_TIMESTAMP)
+            .unwrap();
+        other
+            .mine_blocks(vec!["b".to_string(), "c".to_string()])
+            .unwrap();
+
+        assert!(matches!(
+            app.reorg_to(vec![]),
+            Err(BlockchainError::InvalidChainAt {
+                index: 0,
+                source: BlockError::MissingGenesis
+            })
+        ));
+        assert!(matches!(
+            app.reorg_to(other.blocks),
+            Err(BlockchainError::Chain(ChainError::DifferentGenesis))
+        ));
+        assert_eq!(app.blocks, before);
+    }
+
+    #[test]
+    fn hashes_are_held_against_the_target() {
+        let target = target_from_bits(8);
+        let mut below = target;
+        below[31] -= 1;
+        let mut above = [0; 32];
+        above[0] = 0x01;
+
+        assert_eq!(hex::encode(target), format!("00{}", "ff".repeat(31)));
+        assert!(meets_target(&below, &target));
+        assert!(meets_target(&target, &target));
+        assert!(!meets_target(&above, &target));
+    }
+
+    #[test]
+    fn blocks_from_another_network_are_rejected() {
+        let mut first = App::with_network_id(1);
+        first.genesis();
+        first.mine_blocks(vec!["network 1".to_string()]).unwrap();
+        let mut second = App::with_network_id(2);
+        second.genesis();
+
+        assert_eq!(
+            second.is_block_valid(
+                &first.blocks[1],
+                &second.blocks[0],
+                &second.balances,
+                &second.confirmed_txs,
+                second.next_difficulty()
+            ),
+            Err(BlockError::WrongNetwork)
+        );
+        assert_eq!(second.find_invalid_block(&first.blocks), Some(0));
+    }
+
+    #[test]
+    fn binary_round_trip_is_smaller_than_json() {
+        let mut app = App::init();
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+        let bytes = app.to_bytes();
+
+        assert_eq!(App::from_bytes(&bytes).unwrap().blocks, app.blocks);
+        assert!(bytes.len() < serde_json::to_vec(&app.blocks).unwrap().len());
+    }
+
+    #[test]
+    fn data_is_held_against_the_size_limit() {
+        let mut app = test_app();
+        app.max_data_bytes = 16;
+        let with_data = |len| child_builder(&app.blocks[0]).data("x".repeat(len)).build();
+
+        assert_eq!(check_on_tip(&app, &with_data(15)), Ok(()));
+        assert_eq!(check_on_tip(&app, &with_data(16)), Ok(()));
+        assert_eq!(
+            check_on_tip(&app, &with_data(17)),
+            Err(BlockError::DataTooLarge)
+        );
+        // nine chars, but two bytes each
+        let accented = child_builder(&app.blocks[0]).data("é".repeat(9)).build();
+        assert_eq!(check_on_tip(&app, &accented), Err(BlockError::DataTooLarge));
+        assert_eq!(app.try_next_block("é".repeat(8)).map(|_| ()), Ok(()));
+        assert_eq!(
+            app.try_next_block("é".repeat(9)).map(|_| ()),
+            Err(BlockError::DataTooLarge)
+        );
+        assert!(matches!(
+            Block::new(
+                1,
+                "genesis".to_string(),
+                "x".repeat(DEFAULT_MAX_DATA_BYTES + 1),
+                Difficulty::ZERO,
+                HashAlgorithm::Sha256
+            ),
+            Err(BlockError::DataTooLarge)
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_mining_gives_a_valid_block() {
+        let mut app = App::init();
+        let genesis = app.blocks[0].clone();
+
+        let block = Block::new_async(1, genesis.hash, "mined async".to_string()).await;
+
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn hex_zeros_are_four_bits_each() {
+        assert_eq!(Difficulty::from_hex_zeros(1), Difficulty::from_bits(4));
+        assert_eq!(Difficulty::from_hex_zeros(2).bits(), 8);
+    }
+
+    #[test]
+    fn difficulty_is_capped_at_the_bits_of_a_hash() {
+        assert_eq!(Difficulty::from_bits(300), Difficulty::MAX);
+        assert_eq!(Difficulty::from_hex_zeros(u32::MAX), Difficulty::MAX);
+        assert_eq!(Difficulty::try_from_bits(256), Ok(Difficulty::MAX));
+        assert_eq!(
+            Difficulty::try_from_bits(257),
+            Err(ConfigError::DifficultyTooHigh(257))
+        );
+    }
+
+    #[test]
+    fn supply_is_the_sum_of_the_block_rewards() {
+        let mut app = test_app();
+        app.miner_address = Some("miner".to_string());
+        app.mine_blocks((1..=5).map(|i| format!("block {}", i)).collect())
+            .unwrap();
+
+        let rewards: u64 = (1..=5).map(App::block_reward).sum();
+        assert_eq!(app.total_supply(), rewards);
+        assert_eq!(app.balance_of("miner"), rewards as i64);
+    }
+
+    #[test]
+    fn headers_of_a_valid_chain_validate() {
+        let mut app = App::init();
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+        let mut headers = app.headers();
+        assert_eq!(app.validate_headers(&headers), Ok(()));
+
+        headers[2].previous_hash = headers[0].hash.clone();
+        assert_eq!(
+            app.validate_headers(&headers),
+            Err(BlockError::PreviousHashMismatch)
+        );
+    }
+
+    #[test]
+    fn spending_the_same_coins_twice_in_a_block_is_rejected() {
+        let alice = key(1);
+        let app = funded_app(&alice, 100);
+        let block = child_builder(app.blocks.last().unwrap())
+            .data("double spend")
+            .transactions(vec![
+                transfer(&alice, "bob", 60),
+                transfer(&alice, "carol", 50),
+            ])
+            .build();
+
+        assert_eq!(
+            check_on_tip(&app, &block),
+            Err(BlockError::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    fn mine_pending_skips_transactions_the_sender_can_not_afford() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        let overspend = transfer(&alice, "bob", 500);
+        app.add_to_mempool(overspend.clone()).unwrap();
+        app.add_to_mempool(transfer(&alice, "bob", 10)).unwrap();
+        let height = app.blocks.len();
+
+        app.mine_pending().unwrap();
+        assert_eq!(app.blocks.len(), height + 1);
+        assert_eq!(app.balance_of("bob"), 10);
+        assert_eq!(app.mempool, vec![overspend]);
+    }
+
+    // adds a block mined on the tip with the given timestamp
+    fn add_at(app: &mut App, timestamp: i64) {
+        let block = child_builder(app.blocks.last().unwrap())
+            .data(format!("at {}", timestamp))
+            .timestamp(timestamp)
+            .difficulty(app.next_difficulty())
+            .build();
+        app.try_add_block(block).unwrap();
+    }
+
+    #[test]
+    fn average_block_time_spans_the_window() {
+        let mut app = test_app();
+        for offset in [10, 30, 60] {
+            add_at(&mut app, GENESIS_TIMESTAMP + offset);
+        }
+
+        assert_eq!(app.average_block_time(4), Some(Duration::from_secs(20)));
+        assert_eq!(app.average_block_time(3), Some(Duration::from_secs(25)));
+        assert_eq!(app.average_block_time(5), None);
+    }
+
+    #[test]
+    fn stream_of_a_mined_chain_validates() {
+        let chain = test_blocks(&["one", "two", "three"]);
+        let app = test_app();
+        assert_eq!(app.validate_stream(chain.clone().into_iter()), Ok(4));
+
+        let mut broken = chain;
+        broken[2].data = "broken".to_string();
+        assert!(matches!(
+            app.validate_stream(broken.into_iter()),
+            Err((2, BlockError::HashMismatch))
+        ));
+    }
+
+    #[test]
+    fn genesis_data_changes_the_genesis_hash() {
+        let mut first = App::new();
+        first
+            .genesis_with("first".to_string(), GENESIS_TIMESTAMP)
+            .unwrap();
+        let mut second = App::new();
+        second
+            .genesis_with("second".to_string(), GENESIS_TIMESTAMP)
+            .unwrap();
+
+        assert_ne!(first.blocks[0].hash, second.blocks[0].hash);
+        assert!(matches!(
+            first.genesis_with("again".to_string(), GENESIS_TIMESTAMP),
+            Err(BlockchainError::Chain(ChainError::GenesisExists))
+        ));
+    }
+
+    #[test]
+    fn from_blocks_validates_the_chain() {
+        let mut app = App::init();
+        app.mine_blocks(vec![
+            "one".to_string(),
+            "two".to_string(),
+            "three".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(
+            App::from_blocks(app.blocks.clone()).unwrap().blocks,
+            app.blocks
+        );
+
+        assert!(matches!(
+            App::from_blocks(vec![]),
+            Err((0, BlockError::MissingGenesis))
+        ));
+        let mut broken = app.blocks.clone();
+        broken[2].previous_hash = broken[0].hash.clone();
+        assert!(matches!(App::from_blocks(broken), Err((2, _))));
+    }
+
+    #[test]
+    fn configured_apps_load_chains_they_mined() {
+        let mut blake3 = App::with_hash_algorithm(HashAlgorithm::Blake3);
+        blake3.genesis();
+        blake3.mine_blocks(vec!["blake3".to_string()]).unwrap();
+        assert!(App::from_blocks(blake3.blocks.clone()).is_err());
+        assert!(App::with_hash_algorithm(HashAlgorithm::Blake3)
+            .with_blocks(blake3.blocks)
+            .is_ok());
+
+        let chain = test_blocks(&["zero"]);
+        assert!(App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(chain)
+            .is_ok());
+
+        let stakes = BTreeMap::from([("alice".to_string(), 1)]);
+        let mut staked = App::with_proof_of_stake(stakes.clone());
+        staked.genesis();
+        staked.mine_blocks(vec!["staked".to_string()]).unwrap();
+        assert!(App::with_proof_of_stake(stakes)
+            .with_blocks(staked.blocks)
+            .is_ok());
+    }
+
+    #[test]
+    fn prefix_hasher_matches_hashing_everything() {
+        let contents = BlockContents {
+            merkle_root: Some("root"),
+            validator: Some("validator"),
+            miner: Some("miner"),
+            network_id: 7,
+            ..BlockContents::new(3, GENESIS_TIMESTAMP, "previous", "data")
+        };
+        let input = HashInput::new(&contents);
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha3_256,
+            HashAlgorithm::Blake3,
+        ] {
+            let hasher = PrefixHasher::new(&input.prefix, algorithm);
+            for nonce in [0, 1, 99, 100_000, u64::MAX] {
+                assert_eq!(
+                    hasher.finish(nonce, &input.suffix),
+                    calculate_hash(&contents, nonce, algorithm)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inclusion_proof_verifies_only_the_genuine_transaction() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        for amount in 1..=3 {
+            app.add_to_mempool(transfer(&alice, "bob", amount)).unwrap();
+        }
+        app.mine_pending().unwrap();
+        let block = app.blocks.last().unwrap().clone();
+        let root = block.merkle_root.as_deref().unwrap();
+        let proof = app.inclusion_proof(block.id, 1).unwrap();
+
+        assert!(verify_inclusion(&block.transactions[1], &proof, root));
+        let mut tampered = block.transactions[1].clone();
+        tampered.amount += 1;
+        assert!(!verify_inclusion(&tampered, &proof, root));
+        assert!(!verify_inclusion(&block.transactions[0], &proof, root));
+        assert_eq!(app.inclusion_proof(block.id, 3), None);
+    }
+
+    #[test]
+    fn tip_goes_stale_after_max_age() {
+        let mut app = test_app();
+        assert!(app.is_stale(Duration::from_secs(60)));
+
+        add_at(&mut app, Utc::now().timestamp());
+        assert!(!app.is_stale(Duration::from_secs(60)));
+        assert!(App::new().is_stale(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn block_fills_up_to_the_transaction_limit() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        app.max_txs_per_block = 3;
+        for amount in 1..=5 {
+            app.add_to_mempool(transfer(&alice, "bob", amount)).unwrap();
+        }
+        app.mine_pending().unwrap();
+
+        let tip = app.blocks.last().unwrap();
+        assert_eq!(tip.transactions.len(), 3);
+        let crowded = child_builder(tip)
+            .data("too many")
+            .transactions(
+                app.mempool
+                    .iter()
+                    .cloned()
+                    .chain([transfer(&alice, "carol", 6), transfer(&alice, "carol", 7)])
+                    .collect(),
+            )
+            .build();
+        assert_eq!(
+            check_on_tip(&app, &crowded),
+            Err(BlockError::TooManyTransactions)
+        );
+    }
+
+    #[test]
+    fn diff_splits_chains_after_the_shared_prefix() {
+        let shared = test_blocks(&["1", "2", "3"]);
+        let mine = extended(&shared, &["a4", "a5"]);
+        let theirs = extended(&shared, &["b4"]);
+        let app = App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(mine.clone())
+            .unwrap();
+
+        let diff = app.diff(&theirs);
+
+        assert_eq!(diff.common_ancestor, Some(3));
+        assert_eq!(diff.only_in_self, mine[4..]);
+        assert_eq!(diff.only_in_other, theirs[4..]);
+    }
+
+    #[test]
+    fn correctly_signed_block_is_accepted() {
+        let mut app = test_app();
+        let mut block = child_builder(&app.blocks[0]).data("signed").build();
+        block.sign(&key(1));
+
+        assert!(block.has_valid_signature());
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn block_signed_with_the_wrong_key_is_rejected() {
+        let mut app = test_app();
+        let mut block = child_builder(&app.blocks[0]).data("signed").build();
+        block.sign(&key(1));
+        let mut other = block.clone();
+        other.sign(&key(2));
+        block.miner_pubkey = other.miner_pubkey;
+
+        assert!(!block.has_valid_signature());
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::InvalidSignature))
+        ));
+    }
+
+    #[test]
+    fn unsigned_block_is_accepted() {
+        let mut app = test_app();
+        let block = child_builder(&app.blocks[0]).data("unsigned").build();
+
+        assert!(block.has_valid_signature());
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn progress_callback_fires_while_mining() {
+        let mut reports = Vec::new();
+        Block::mine_block(
+            1,
+            GENESIS_TIMESTAMP,
+            "genesis",
+            "progress",
+            Difficulty::from_bits(4),
+            HashAlgorithm::Sha256,
+            Some(&mut |progress| reports.push(progress)),
+        );
+
+        assert!(!reports.is_empty());
+        assert_eq!(reports[0].nonce, 0);
+    }
+
+    #[test]
+    fn longer_chain_with_another_genesis_is_rejected() {
+        let local = test_blocks(&["a"]);
+        let mut other = App::with_difficulty(Difficulty::ZERO);
+        other
+            .genesis_with("other network".to_string(), GENESIS_TIMESTAMP)
+            .unwrap();
+        other
+            .mine_blocks(vec!["b".to_string(), "c".to_string(), "d".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            test_app()
+                .choose_chain(local.clone(), other.blocks)
+                .unwrap(),
+            local
+        );
+    }
+
+    #[test]
+    fn changing_data_without_mining_is_flagged() {
+        let mut app = App::test_chain(4);
+        assert!(app.find_tampered().is_empty());
+        assert_eq!(
+            app.recompute_hashes(),
+            app.blocks
+                .iter()
+                .map(|block| block.hash.clone())
+                .collect::<Vec<_>>()
+        );
+
+        app.blocks[2].data = "tampered".to_string();
+        assert_eq!(app.find_tampered(), vec![2]);
+    }
+
+    #[test]
+    fn one_outlier_does_not_move_the_difficulty() {
+        for outlier in [0, 1000] {
+            let mut app = App::with_difficulty(Difficulty::from_bits(2));
+            app.genesis();
+            app.target_block_time = Duration::from_secs(10);
+            let mut timestamp = GENESIS_TIMESTAMP;
+            for interval in [10, 10, 10, outlier, 10, 10] {
+                timestamp += interval;
+                let before = app.next_difficulty();
+                add_at(&mut app, timestamp);
+                assert_eq!(app.next_difficulty(), before);
+            }
+        }
+    }
+
+    #[test]
+    fn difficulty_moves_no_more_than_the_cap_per_block() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(4));
+        app.genesis();
+        let max_step = app.max_retarget_factor.ilog2();
+        let mut difficulties = vec![app.next_difficulty()];
+        let mut timestamp = GENESIS_TIMESTAMP + 100;
+        for interval in [0, 0, 0, 1000, 1000, 1000, 1000, 1000, 1000, 1000] {
+            timestamp += interval;
+            add_at(&mut app, timestamp);
+            difficulties.push(app.next_difficulty());
+        }
+
+        assert!(difficulties
+            .windows(2)
+            .all(|pair| pair[0].bits().abs_diff(pair[1].bits()) <= max_step));
+        // it climbs past where a cap on the total rise would have stopped it, then back down
+        let highest = difficulties.iter().max().unwrap();
+        assert!(highest.bits() > app.difficulty.bits() + max_step);
+        assert_eq!(difficulties.last(), Some(&app.min_difficulty));
+    }
+
+    #[test]
+    fn pruning_keeps_the_retargeted_difficulty() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(2));
+        app.genesis();
+        for _ in 0..5 {
+            add_at(&mut app, GENESIS_TIMESTAMP + 100);
+        }
+        let difficulty = app.next_difficulty();
+        app.prune(2);
+
+        assert_eq!(app.next_difficulty(), difficulty);
+        assert_eq!(app.find_invalid_block(&app.blocks), None);
+        add_at(&mut app, GENESIS_TIMESTAMP + 100);
+    }
+
+    #[test]
+    fn peer_blocks_have_to_meet_the_retargeted_difficulty() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(2));
+        app.genesis();
+        for _ in 0..5 {
+            add_at(&mut app, GENESIS_TIMESTAMP + 100);
+        }
+        let tip = app.blocks.last().unwrap().clone();
+        assert!(app.next_difficulty() > app.difficulty);
+
+        // easy enough for the minimum difficulty, too easy for the retargeted one
+        let too_easy = (0..)
+            .map(|nonce| {
+                child_builder(&tip)
+                    .data("too easy")
+                    .timestamp(GENESIS_TIMESTAMP + 100)
+                    .nonce(nonce)
+                    .build()
+            })
+            .find(|block| {
+                let zeros = leading_zero_bits(&hex::decode(&block.hash).unwrap());
+                zeros >= app.difficulty.bits() && zeros < app.next_difficulty().bits()
+            })
+            .unwrap();
+        assert!(matches!(
+            app.try_add_block(too_easy),
+            Err(BlockchainError::Block(BlockError::InvalidDifficulty))
+        ));
+    }
+
+    #[test]
+    fn snapshot_does_not_see_later_blocks() {
+        let mut app = test_app();
+        app.mine_blocks(vec!["before".to_string()]).unwrap();
+        let snapshot = app.snapshot();
+
+        app.mine_blocks(vec!["after".to_string()]).unwrap();
+
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot.blocks(), &app.blocks[..2]);
+        assert_eq!(snapshot.get(2), None);
+    }
+
+    #[test]
+    fn estimated_mining_time_is_in_the_ballpark() {
+        let app = App::with_difficulty(Difficulty::from_bits(20));
+        let seconds = app.estimated_mine_seconds(1e6);
+        assert!((0.5..2.0).contains(&seconds), "{} seconds", seconds);
+    }
+
+    #[test]
+    fn adding_to_an_empty_chain_needs_a_genesis() {
+        let mut app = App::new();
+        let block = Block::builder()
+            .id(0)
+            .previous_hash("genesis")
+            .data("no genesis")
+            .build();
+
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::MissingGenesis))
+        ));
+        assert!(app.blocks.is_empty());
+    }
+
+    #[test]
+    fn empty_chain_validates() {
+        let mut app = App::new();
+        assert!(app.is_chain_valid(&[]));
+        assert_eq!(app.find_invalid_block(&[]), None);
+    }
+
+    #[test]
+    fn next_block_fails_without_a_genesis_or_a_validator() {
+        assert_eq!(
+            App::new().try_next_block("no genesis".to_string()),
+            Err(BlockError::MissingGenesis)
+        );
+
+        let mut app = App::with_proof_of_stake(BTreeMap::new());
+        app.genesis();
+        assert_eq!(
+            app.try_next_block("no stakers".to_string()),
+            Err(BlockError::InvalidValidator)
+        );
+        assert!(matches!(
+            app.mine_blocks(vec!["no stakers".to_string()]),
+            Err(BlockchainError::Block(BlockError::InvalidValidator))
+        ));
+    }
+
+    #[test]
+    fn genesis_allocations_show_up_as_balances() {
+        let mut app = App::with_difficulty(Difficulty::ZERO);
+        app.genesis_with_allocations(HashMap::from([
+            ("alice".to_string(), 70),
+            ("bob".to_string(), 30),
+        ]))
+        .unwrap();
+
+        assert_eq!(app.balance_of("alice"), 70);
+        assert_eq!(app.balance_of("bob"), 30);
+        assert_eq!(app.balance_of("carol"), 0);
+        assert_eq!(app.total_supply(), 100);
+    }
+
+    #[test]
+    fn exported_block_is_accepted_by_a_fresh_chain() {
+        let app = App::init();
+        let json = app.next_block("exported".to_string()).to_json();
+
+        let mut fresh = App::init();
+        fresh.accept_external_block(&json).unwrap();
+
+        assert_eq!(fresh.blocks.len(), 2);
+        assert_eq!(fresh.blocks[1].to_json(), json);
+    }
+
+    #[test]
+    fn equal_chains_are_decided_by_the_smaller_tip_hash() {
+        let genesis = test_app().blocks;
+        let fork = |data: String| {
+            let mut chain = genesis.clone();
+            chain.push(child_builder(&genesis[0]).data(data).build());
+            chain
+        };
+        // two forks of the same length whose tips have the same number of zero bits do equal work
+        let mut forks: Vec<Vec<Block>> = Vec::new();
+        for i in 0.. {
+            let chain = fork(format!("fork {}", i));
+            if let Some(twin) = forks
+                .iter()
+                .find(|other| App::total_work(other) == App::total_work(&chain))
+            {
+                forks = vec![twin.clone(), chain];
+                break;
+            }
+            forks.push(chain);
+        }
+        let (a, b) = (forks[0].clone(), forks[1].clone());
+        let smaller = if a[1].hash < b[1].hash {
+            a.clone()
+        } else {
+            b.clone()
+        };
+
+        assert_eq!(
+            test_app().choose_chain(a.clone(), b.clone()).unwrap(),
+            smaller
+        );
+        assert_eq!(test_app().choose_chain(b, a).unwrap(), smaller);
+    }
+
+    #[test]
+    fn legacy_json_loads_as_version_1() {
+        let json =
+            r#"{"id":1,"hash":"00","previous_hash":"00","timestamp":0,"data":"legacy","nonce":0}"#;
+        let block = Block::from_json(json).unwrap();
+
+        assert_eq!(block.version, 1);
+        assert_eq!(block.version, BLOCK_VERSION);
+    }
+
+    #[test]
+    fn zero_difficulty_mines_nonce_0_and_still_links() {
+        let result = Block::mine_block(
+            1,
+            GENESIS_TIMESTAMP,
+            "genesis",
+            "instant",
+            Difficulty::ZERO,
+            HashAlgorithm::Sha256,
+            Some(&mut |_| {}),
+        );
+        assert_eq!((result.nonce, result.attempts), (0, 1));
+
+        let chain = test_blocks(&["one", "two"]);
+        assert!(chain[1..].iter().all(|block| block.nonce == 0));
+        assert!(chain
+            .windows(2)
+            .all(|pair| pair[1].previous_hash == pair[0].hash));
+        assert_eq!(test_app().find_invalid_block(&chain), None);
+    }
+
+    #[test]
+    fn chains_have_to_match_the_checkpoint() {
+        let genesis = test_app().blocks;
+        let chain = extended(&genesis, &["1", "2", "3"]);
+        let fork = extended(&chain[..2], &["other 2", "other 3"]);
+        let mut app = test_app();
+        app.set_checkpoint(2, chain[2].hash.clone());
+
+        assert_eq!(app.find_invalid_block(&chain), None);
+        assert_eq!(app.find_invalid_block(&fork), Some(2));
+        assert_eq!(
+            app.validate_stream(fork.into_iter()),
+            Err((2, BlockError::CheckpointMismatch))
+        );
+    }
+
+    #[test]
+    fn nonce_range_finds_a_solution_only_when_it_holds_one() {
+        let difficulty = Difficulty::from_bits(8);
+        let first = Block::mine_block(
+            1,
+            GENESIS_TIMESTAMP,
+            "genesis",
+            "sharded",
+            difficulty,
+            HashAlgorithm::Sha256,
+            Some(&mut |_| {}),
+        );
+        let range = |start, end| {
+            Block::mine_block_range(
+                1,
+                GENESIS_TIMESTAMP,
+                "genesis",
+                "sharded",
+                difficulty,
+                HashAlgorithm::Sha256,
+                start,
+                end,
+            )
+        };
+
+        assert_eq!(range(0, first.nonce + 1), Some((first.nonce, first.hash)));
+        assert_eq!(range(0, first.nonce), None);
+        assert_eq!(range(first.nonce, first.nonce), None);
+    }
+
+    #[test]
+    fn errors_say_what_went_wrong() {
+        assert!(matches!(
+            App::load_from_file(&temp_path("missing.json")),
+            Err(BlockchainError::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(matches!(
+            App::from_bytes(b"not bincode"),
+            Err(BlockchainError::Bincode(_))
+        ));
+        assert!(matches!(
+            BlockchainError::from((3, BlockError::HashMismatch)),
+            BlockchainError::InvalidChainAt {
+                index: 3,
+                source: BlockError::HashMismatch
+            }
+        ));
+
+        let mut app = App::new();
+        assert!(matches!(
+            app.set_difficulty_prefix("0x"),
+            Err(ConfigError::InvalidDifficultyPrefix('x'))
+        ));
+        app.genesis();
+        assert!(matches!(
+            app.genesis_with("second".to_string(), GENESIS_TIMESTAMP),
+            Err(BlockchainError::Chain(ChainError::GenesisExists))
+        ));
+    }
+
+    #[test]
+    fn incremental_balances_match_a_full_replay() {
+        let alice = key(1);
+        let bob = key(2);
+        let mut app = funded_app(&alice, 1000);
+        app.miner_address = Some("miner".to_string());
+        for i in 1..=10 {
+            app.add_to_mempool(transfer(&alice, &address_of(&bob), 10 * i))
+                .unwrap();
+            if i > 1 {
+                app.add_to_mempool(transfer_with_fee(&bob, "carol", i, 1))
+                    .unwrap();
+            }
+            app.mine_pending().unwrap();
+        }
+
+        assert_eq!(app.blocks.len(), 11);
+        assert_eq!(app.balances, app.replay_balances());
+    }
+
+    #[test]
+    fn reorg_after_pruning_keeps_balances_right() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 1000);
+        let genesis = app.blocks.clone();
+        for amount in [100, 200, 300] {
+            app.add_to_mempool(transfer(&alice, "bob", amount)).unwrap();
+            app.mine_pending().unwrap();
+        }
+        app.prune(2);
+
+        let mut other = App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(genesis)
+            .unwrap();
+        for amount in [50, 100, 150, 40] {
+            other
+                .add_to_mempool(transfer(&alice, "bob", amount))
+                .unwrap();
+            other.mine_pending().unwrap();
+        }
+        app.reorg_to(other.blocks.clone()).unwrap();
+
+        assert_eq!(app.blocks, other.blocks);
+        assert_eq!(app.balance_of("bob"), 340);
+        assert_eq!(app.pruned_tip, None);
+        assert_eq!(app.balances, app.replay_balances());
+    }
+
+    #[test]
+    fn difficulty_series_follows_the_hashes() {
+        let mut app = test_app();
+        let mined_at = [0, 8, 4, 6];
+        for bits in mined_at {
+            let block = child_builder(app.blocks.last().unwrap())
+                .data(format!("{} bits", bits))
+                .difficulty(Difficulty::from_bits(bits))
+                .build();
+            app.try_add_block(block).unwrap();
+        }
+
+        let series = app.difficulty_over_time();
+
+        assert_eq!(
+            series,
+            app.blocks
+                .iter()
+                .map(|block| (block.id, block.hash_zero_bits()))
+                .collect::<Vec<_>>()
+        );
+        for (&(_, bits), min) in series[1..].iter().zip(mined_at) {
+            assert!(bits >= min);
+        }
+    }
+
+    #[test]
+    fn display_shows_the_id_and_a_short_hash() {
+        let app = App::test_chain(2);
+        let block = &app.blocks[1];
+        let shown = block.to_string();
+
+        assert!(shown.starts_with("#1 "));
+        assert!(shown.contains(&block.hash[..8]));
+        assert!(!shown.contains(&block.hash));
+    }
+
+    #[test]
+    fn reorgs_deeper_than_the_limit_are_rejected() {
+        let genesis = test_app().blocks;
+        let local = extended(&genesis, &["a1", "a2"]);
+        let mut app = App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(local.clone())
+            .unwrap();
+        app.max_reorg_depth = Some(1);
+
+        let deep = extended(&genesis, &["c1", "c2", "c3"]);
+        assert!(matches!(
+            app.reorg_to(deep),
+            Err(BlockchainError::Chain(ChainError::ReorgTooDeep))
+        ));
+        assert_eq!(app.blocks, local);
+
+        let shallow = extended(&local[..2], &["b2", "b3"]);
+        assert_eq!(app.reorg_to(shallow.clone()).unwrap().rolled_back, 1);
+        assert_eq!(app.blocks, shallow);
+    }
+
+    #[test]
+    fn work_proof_changes_with_order_and_difficulty() {
+        let chain = test_blocks(&["1", "2", "3"]);
+        let proof_of = |blocks: Vec<Block>| {
+            let mut app = App::with_difficulty(Difficulty::ZERO);
+            app.blocks = blocks;
+            app.work_proof()
+        };
+        let proof = proof_of(chain.clone());
+        assert!(App::verify_work_proof(&proof, &proof_of(chain.clone())));
+        assert!(App::verify_work_proof(&proof, &proof.to_uppercase()));
+
+        let mut reordered = chain.clone();
+        reordered.swap(1, 2);
+        assert_ne!(proof_of(reordered), proof);
+
+        let mut harder = chain.clone();
+        harder[2] = child_builder(&chain[1])
+            .data(chain[2].data.clone())
+            .timestamp(chain[2].timestamp)
+            .difficulty(Difficulty::from_bits(8))
+            .build();
+        assert_ne!(proof_of(harder), proof);
+    }
+
+    #[test]
+    fn typed_payload_blocks_mine_and_validate() {
+        let difficulty = Difficulty::from_bits(4);
+        let algorithm = HashAlgorithm::Sha256;
+        let genesis: Block<Vec<u32>> = Block::mine_payload(
+            0,
+            GENESIS_TIMESTAMP,
+            "genesis",
+            vec![],
+            difficulty,
+            algorithm,
+        );
+        let mut block = Block::mine_payload(
+            1,
+            GENESIS_TIMESTAMP + 10,
+            &genesis.hash,
+            vec![1, 2, 3],
+            difficulty,
+            algorithm,
+        );
+        assert_eq!(
+            block.validate_payload(&genesis, difficulty, algorithm),
+            Ok(())
+        );
+
+        block.data.push(4);
+        assert_eq!(
+            block.validate_payload(&genesis, difficulty, algorithm),
+            Err(BlockError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn hashrate_is_positive_and_finite() {
+        let rate = App::init().benchmark_hashrate(Duration::from_millis(50));
+        assert!(rate.is_finite() && rate > 0.0, "{} H/s", rate);
+    }
+
+    #[test]
+    fn id_sequence_gaps_and_duplicates_are_found() {
+        let with_ids = |ids: &[u64]| -> Vec<Block> {
+            ids.iter()
+                .map(|&id| Block::builder().id(id).data("id").nonce(0).build())
+                .collect()
+        };
+
+        assert_eq!(App::verify_id_sequence(&with_ids(&[0, 1, 2])), Ok(()));
+        assert_eq!(App::verify_id_sequence(&with_ids(&[0, 1, 3])), Err(3));
+        assert_eq!(App::verify_id_sequence(&with_ids(&[0, 1, 1])), Err(1));
+    }
+
+    #[test]
+    fn empty_data_is_mined_only_when_allowed() {
+        let mut app = test_app();
+        assert!(app.allow_empty_data);
+        app.mine_blocks(vec![String::new()]).unwrap();
+        assert_eq!(app.blocks[1].data, "");
+
+        app.allow_empty_data = false;
+        assert_eq!(
+            app.try_next_block(String::new()),
+            Err(BlockError::EmptyData)
+        );
+        assert!(matches!(
+            app.mine_pending(),
+            Err(BlockchainError::Block(BlockError::EmptyData))
+        ));
+        let block = child_builder(&app.blocks[1]).build();
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::EmptyData))
+        ));
+        // a block on its own doesn't know the flag
+        assert!(Block::new(
+            2,
+            app.blocks[1].hash.clone(),
+            String::new(),
+            Difficulty::ZERO,
+            HashAlgorithm::Sha256
+        )
+        .is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn next_block_panics_on_refused_data() {
+        let mut app = test_app();
+        app.allow_empty_data = false;
+        app.next_block(String::new());
+    }
+
+    #[test]
+    fn archive_round_trips() {
+        let app = App::test_chain(4);
+        let mut archive = Vec::new();
+        app.export_archive(&mut archive).unwrap();
+
+        assert_eq!(
+            App::import_archive(&archive[..]).unwrap().blocks,
+            app.blocks
+        );
+    }
+
+    #[test]
+    fn archive_with_corrupt_magic_is_refused() {
+        let app = App::test_chain(2);
+        let mut archive = Vec::new();
+        app.export_archive(&mut archive).unwrap();
+        archive[0] ^= 0xff;
+
+        assert!(matches!(
+            App::import_archive(&archive[..]),
+            Err(BlockchainError::NotAnArchive)
+        ));
+    }
+
+    #[test]
+    fn genesis_weaker_than_later_blocks_is_valid() {
+        let difficulty = Difficulty::from_bits(8);
+        let mut app = App::with_difficulty(difficulty);
+        app.genesis();
+        assert!(app.blocks[0].hash_zero_bits() < difficulty.bits());
+
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+
+        assert!(app.blocks[1..]
+            .iter()
+            .all(|block| block.hash_zero_bits() >= difficulty.bits()));
+        assert_eq!(app.find_invalid_block(&app.blocks), None);
+        assert!(App::with_difficulty(difficulty)
+            .with_blocks(app.blocks)
+            .is_ok());
+    }
+
+    #[test]
+    fn highest_fees_are_included_first() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        app.miner_address = Some("miner".to_string());
+        app.max_txs_per_block = 2;
+        for (amount, fee) in [(10, 1), (11, 5), (12, 3)] {
+            app.add_to_mempool(transfer_with_fee(&alice, "bob", amount, fee))
+                .unwrap();
+        }
+        app.mine_pending().unwrap();
+
+        let block = &app.blocks[1];
+        let fees: Vec<_> = block.transactions.iter().map(|tx| tx.fee).collect();
+        assert_eq!(fees, vec![5, 3]);
+        assert_eq!(block.reward, App::block_reward(1) + 8);
+        assert_eq!(app.balance_of("miner"), (App::block_reward(1) + 8) as i64);
+        assert_eq!(app.balance_of(&address_of(&alice)), 100 - 11 - 5 - 12 - 3);
+        assert_eq!(app.mempool.len(), 1);
+    }
+
+    #[test]
+    fn transactions_are_found_in_their_block() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        app.mine_blocks(vec!["empty".to_string()]).unwrap();
+        let tx = transfer(&alice, "bob", 10);
+        app.add_to_mempool(transfer(&alice, "bob", 5)).unwrap();
+        app.add_to_mempool(tx.clone()).unwrap();
+        app.mine_pending().unwrap();
+
+        let index = app.blocks[2]
+            .transactions
+            .iter()
+            .position(|included| *included == tx)
+            .unwrap();
+        assert_eq!(app.find_transaction(&tx.hash()), Some((2, index)));
+        assert_eq!(
+            app.find_transaction(&transfer(&alice, "bob", 99).hash()),
+            None
+        );
+    }
+
+    #[test]
+    fn block_with_a_non_hex_hash_is_rejected_without_panicking() {
+        let mut app = App::init();
+        let block = Block::builder()
+            .id(1)
+            .previous_hash(app.blocks[0].hash.clone())
+            .data("not hex")
+            .nonce(0)
+            .hash("zz".repeat(32))
+            .build();
+
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::InvalidHashEncoding))
+        ));
+        assert_eq!(app.blocks.len(), 1);
+    }
+
+    #[test]
+    fn validated_prefix_is_not_validated_again() {
+        let mut app = test_app();
+        let checked = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&checked);
+        app.add_validation_rule(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        });
+        app.mine_blocks((1..10).map(|i| format!("block {}", i)).collect())
+            .unwrap();
+        assert_eq!(checked.swap(0, Ordering::Relaxed), 9);
+
+        app.mine_blocks(vec!["one more".to_string()]).unwrap();
+        assert_eq!(checked.swap(0, Ordering::Relaxed), 1);
+
+        let mut longer = app.blocks.clone();
+        longer.push(app.next_block("from a peer".to_string()));
+        assert!(app.is_chain_valid(&longer));
+        assert_eq!(checked.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn custom_validation_rules_are_enforced() {
+        let mut app = test_app();
+        app.add_validation_rule(|block, _| {
+            if block.data == block.data.to_uppercase() {
+                Ok(())
+            } else {
+                Err(BlockError::RuleViolation)
+            }
+        });
+
+        app.mine_blocks(vec!["UPPERCASE".to_string()]).unwrap();
+        assert!(matches!(
+            app.mine_blocks(vec!["lowercase".to_string()]),
+            Err(BlockchainError::Block(BlockError::RuleViolation))
+        ));
+        assert_eq!(app.blocks.len(), 2);
+    }
+
+    #[test]
+    fn chain_duration_and_block_rate_use_the_timestamps() {
+        let mut app = test_app();
+        assert_eq!(app.chain_duration(), None);
+        add_at(&mut app, GENESIS_TIMESTAMP + 600);
+        add_at(&mut app, GENESIS_TIMESTAMP + 1800);
+
+        assert_eq!(app.chain_duration(), Some(Duration::from_secs(1800)));
+        assert_eq!(app.blocks_per_hour(), Some(4.0));
+    }
+
+    #[test]
+    fn same_seed_mines_the_same_block() {
+        let mut app = App::init();
+        app.seed_timestamp = Some(GENESIS_TIMESTAMP + 100);
+
+        let first = app.next_block("seeded".to_string());
+        let second = app.next_block("seeded".to_string());
+
+        assert_eq!((first.nonce, &first.hash), (second.nonce, &second.hash));
+    }
+
+    #[test]
+    fn blocks_too_soon_after_their_parent_are_rejected() {
+        let mut app = test_app();
+        app.min_interval = Duration::from_secs(60);
+        let too_soon = child_builder(&app.blocks[0]).data("too soon").build();
+        assert!(matches!(
+            app.try_add_block(too_soon),
+            Err(BlockchainError::Block(BlockError::TooSoonAfterParent))
+        ));
+
+        add_at(&mut app, GENESIS_TIMESTAMP + 60);
+        assert_eq!(app.blocks.len(), 2);
+    }
+
+    #[test]
+    fn mine_blocks_adds_one_block_per_data() {
+        let mut app = test_app();
+        let ids = app
+            .mine_blocks((1..=5).map(|i| format!("block {}", i)).collect())
+            .unwrap();
+
+        assert_eq!(app.blocks.len(), 6);
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+        assert!(app.blocks.iter().map(|block| block.id).eq(0..6));
+    }
+
+    #[test]
+    fn verify_self_catches_a_tampered_nonce() {
+        let difficulty = Difficulty::from_bits(4);
+        let mut block = Block::builder()
+            .id(1)
+            .previous_hash("genesis")
+            .data("self contained")
+            .timestamp(GENESIS_TIMESTAMP)
+            .difficulty(difficulty)
+            .build();
+        assert_eq!(block.verify_self(difficulty, HashAlgorithm::Sha256), Ok(()));
+
+        block.nonce += 1;
+        assert_eq!(
+            block.verify_self(difficulty, HashAlgorithm::Sha256),
+            Err(BlockError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn difficulty_prefix_is_used_for_mining_and_validation() {
+        let mut app = App::init();
+        app.set_difficulty_prefix("0000").unwrap();
+        let block = app.next_block("prefixed".to_string());
+
+        let hash = hex::decode(&block.hash).unwrap();
+        assert!(hash_to_binary_representation(&hash).starts_with("0000"));
+        assert!(app.try_add_block(block).is_ok());
+    }
+
+    #[test]
+    fn bad_difficulty_prefixes_are_refused() {
+        let mut app = App::init();
+        assert_eq!(
+            app.set_difficulty_prefix(""),
+            Err(ConfigError::EmptyDifficultyPrefix)
+        );
+        assert_eq!(
+            app.set_difficulty_prefix("0020"),
+            Err(ConfigError::InvalidDifficultyPrefix('2'))
+        );
+        assert_eq!(
+            app.set_difficulty_prefix(&"0".repeat(257)),
+            Err(ConfigError::DifficultyPrefixTooLong(257))
+        );
+        assert_eq!(app.difficulty_prefix, None);
+    }
+
+    #[test]
+    fn subscribers_hear_about_added_blocks() {
+        let mut app = test_app();
+        let events = app.subscribe();
+
+        app.mine_blocks(vec!["announced".to_string()]).unwrap();
+
+        match events.try_recv() {
+            Ok(ChainEvent::BlockAdded(block)) => assert_eq!(block.id, 1),
+            other => panic!("expected BlockAdded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn incremental_merkle_root_matches_the_batch_one() {
+        for count in [1, 2, 3, 8] {
+            let txs: Vec<String> = (0..count).map(|i| format!("tx {}", i)).collect();
+            let mut accumulator = MerkleAccumulator::new();
+            for tx in &txs {
+                accumulator
+                    .push(&hex::encode(Sha256::digest(tx.as_bytes())))
+                    .unwrap();
+            }
+
+            assert_eq!(accumulator.len(), count);
+            assert_eq!(
+                accumulator.root(),
+                merkle_root(&txs),
+                "{} transactions",
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn nonce_not_producing_the_hash_is_a_hash_mismatch() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(8));
+        app.genesis();
+        let mut block = child_builder(&app.blocks[0])
+            .data("mined")
+            .difficulty(app.difficulty)
+            .build();
+        block.nonce += 1;
+
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::HashMismatch))
+        ));
+    }
+
+    #[test]
+    fn hash_not_meeting_the_difficulty_is_invalid_difficulty() {
+        let mut app = App::with_difficulty(Difficulty::from_bits(8));
+        app.genesis();
+        let block = (0..)
+            .map(|nonce| {
+                child_builder(&app.blocks[0])
+                    .data("too easy")
+                    .nonce(nonce)
+                    .build()
+            })
+            .find(|block| block.hash_zero_bits() < 8)
+            .unwrap();
+
+        assert!(matches!(
+            app.try_add_block(block),
+            Err(BlockchainError::Block(BlockError::InvalidDifficulty))
+        ));
+    }
+
+    #[test]
+    fn fresh_apps_have_the_same_genesis() {
+        assert_eq!(App::init().blocks, App::init().blocks);
+        assert_eq!(App::init().genesis_timestamp, Some(GENESIS_TIMESTAMP));
+    }
+
+    #[test]
+    fn genesis_timestamp_comes_from_the_chain_unless_pinned() {
+        let mut app = App::new();
+        app.genesis_with("custom".to_string(), GENESIS_TIMESTAMP + 123)
+            .unwrap();
+
+        let loaded = App::from_blocks(app.blocks.clone()).unwrap();
+        assert_eq!(loaded.genesis_timestamp, Some(GENESIS_TIMESTAMP + 123));
+
+        let mut pinned = App::new();
+        pinned.genesis_timestamp = Some(GENESIS_TIMESTAMP);
+        assert!(matches!(
+            pinned.with_blocks(app.blocks),
+            Err((0, BlockError::GenesisTimestampMismatch))
+        ));
+    }
+
+    #[test]
+    fn first_overspend_in_the_chain_is_reported() {
+        let alice = key(1);
+        let mut app = funded_app(&alice, 100);
+        for amount in [10, 20] {
+            app.add_to_mempool(transfer(&alice, "bob", amount)).unwrap();
+            app.mine_pending().unwrap();
+        }
+        assert_eq!(app.first_invalid_transaction(), None);
+
+        // past validation, the way a chain loaded without checks could hold it
+        let block = child_builder(&app.blocks[2])
+            .data("overspend")
+            .transactions(vec![
+                transfer(&alice, "bob", 30),
+                transfer(&alice, "carol", 500),
+            ])
+            .build();
+        app.blocks.push(block);
+
+        assert!(matches!(
+            app.first_invalid_transaction(),
+            Some((3, 1, TxError::InsufficientFunds { .. }))
+        ));
+    }
+}
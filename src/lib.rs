@@ -0,0 +1,5 @@
+pub mod app;
+pub mod light_client;
+pub mod p2p;
+pub mod store;
+pub mod transaction;
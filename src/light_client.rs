@@ -0,0 +1,102 @@
+use crate::app::{validate_header, BlockError, BlockHeader, Difficulty};
+use log::warn;
+
+// tracks a proof of work chain by its headers alone, the way an SPV wallet does
+// every header has to link to the one before it and meet the difficulty, the block data
+// is never seen, so hashes can't be recomputed and transactions aren't checked
+pub struct LightClient {
+    pub headers: Vec<BlockHeader>,
+    // least number of zero bits every header after genesis needs
+    pub difficulty: Difficulty,
+}
+
+impl LightClient {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            headers: vec![],
+            difficulty,
+        }
+    }
+
+    // appends the header after the current tip, the first one has to be the genesis header,
+    // which like in `App` is exempt from the difficulty
+    pub fn receive_header(&mut self, header: BlockHeader) -> Result<(), BlockError> {
+        match self.headers.last() {
+            Some(tip) => validate_header(&header, tip, Some(self.difficulty))?,
+            None if header.id != 0 => {
+                warn!(
+                    "header id: {}, received before the genesis header",
+                    header.id
+                );
+                return Err(BlockError::MissingGenesis);
+            }
+            None => {}
+        }
+        self.headers.push(header);
+        Ok(())
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+
+    // id of the tip, 0 while there are no headers yet
+    pub fn height(&self) -> u64 {
+        self.tip().map_or(0, |tip| tip.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    fn mined_headers() -> (App, Vec<BlockHeader>) {
+        let mut app = App::init();
+        app.mine_blocks(vec![
+            "one".to_string(),
+            "two".to_string(),
+            "three".to_string(),
+        ])
+        .unwrap();
+        let headers = app.headers();
+        (app, headers)
+    }
+
+    #[test]
+    fn valid_header_stream_is_followed() {
+        let (app, headers) = mined_headers();
+        let mut client = LightClient::new(app.difficulty);
+
+        for header in headers {
+            client.receive_header(header).unwrap();
+        }
+
+        assert_eq!(client.height(), 3);
+        assert_eq!(
+            client.tip().map(|tip| tip.hash.as_str()),
+            app.blocks.last().map(|block| block.hash.as_str())
+        );
+    }
+
+    #[test]
+    fn broken_header_stream_is_rejected() {
+        let (app, headers) = mined_headers();
+        let mut client = LightClient::new(app.difficulty);
+        assert_eq!(
+            client.receive_header(headers[1].clone()),
+            Err(BlockError::MissingGenesis)
+        );
+
+        client.receive_header(headers[0].clone()).unwrap();
+        client.receive_header(headers[1].clone()).unwrap();
+        assert!(client.receive_header(headers[3].clone()).is_err());
+        let mut forged = headers[2].clone();
+        forged.previous_hash = headers[0].hash.clone();
+        assert_eq!(
+            client.receive_header(forged),
+            Err(BlockError::PreviousHashMismatch)
+        );
+        assert_eq!(client.height(), 1);
+    }
+}
@@ -1,3 +1,45 @@
-fn main() {
-    println!("Hello, world!");
+use clap::{Parser, Subcommand};
+use rust_blockchain_example::app::{App, BlockchainError};
+use std::{error::Error, io, path::PathBuf};
+
+#[derive(Parser)]
+#[command(about = "mine blocks onto a local blockchain")]
+struct Cli {
+    /// file the chain is loaded from and saved back to
+    #[arg(long, default_value = "chain.json")]
+    chain: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// mines a new block carrying the data and appends it to the chain
+    Mine { data: String },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    pretty_env_logger::init();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Mine { data } => {
+            // a missing file just means there is no chain yet, so start from genesis
+            let mut app = match App::load_from_file(&cli.chain) {
+                Ok(app) => app,
+                Err(BlockchainError::Io(e)) if e.kind() == io::ErrorKind::NotFound => App::init(),
+                Err(e) => return Err(e.into()),
+            };
+
+            let block = app.try_next_block(data)?;
+            let (id, nonce, hash) = (block.id, block.nonce, block.hash.clone());
+            app.try_add_block(block)?;
+            app.save_to_file(&cli.chain)?;
+
+            println!("id: {}", id);
+            println!("nonce: {}", nonce);
+            println!("hash: {}", hash);
+        }
+    }
+    Ok(())
 }
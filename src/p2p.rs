@@ -0,0 +1,90 @@
+use crate::app::{App, Block};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+// messages peers exchange to keep their chains in sync
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Message {
+    // asks a peer to send its whole chain
+    RequestChain,
+    // answer to `RequestChain`
+    ChainResponse(Vec<Block>),
+    // a freshly mined block
+    NewBlock(Box<Block>),
+}
+
+impl App {
+    // applies a message from a peer to the local chain, returns the reply if one is due
+    // a remote chain goes through `choose_chain` and `reorg_to`, a new block through `try_add_block`
+    pub fn handle_message(&mut self, msg: Message) -> Option<Message> {
+        match msg {
+            Message::RequestChain => Some(Message::ChainResponse(self.blocks.clone())),
+            Message::ChainResponse(remote) => {
+                let local = self.blocks.clone();
+                match self
+                    .choose_chain(local, remote)
+                    .and_then(|chain| self.reorg_to(chain))
+                {
+                    Ok(report) if report.added > 0 => {
+                        info!("switched to a peer chain, {} new blocks", report.added)
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("ignoring chain response: {}", e),
+                }
+                None
+            }
+            Message::NewBlock(block) => {
+                let id = block.id;
+                match self.try_add_block(*block) {
+                    Ok(()) => info!("added block id: {} from a peer", id),
+                    Err(e) => warn!("rejected block id: {} from a peer: {}", id, e),
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(chain: &[Block]) -> Vec<String> {
+        chain.iter().map(|block| block.hash.clone()).collect()
+    }
+
+    #[test]
+    fn chain_requests_are_answered_with_the_chain() {
+        let mut app = App::init();
+        match app.handle_message(Message::RequestChain) {
+            Some(Message::ChainResponse(blocks)) => {
+                assert_eq!(hashes(&blocks), hashes(&app.blocks))
+            }
+            other => panic!("expected the chain, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn longer_chain_from_a_peer_is_adopted() {
+        let mut app = App::init();
+        let mut peer = App::init();
+        let block = peer.next_block("from peer".to_string());
+        peer.try_add_block(block).unwrap();
+
+        assert!(app
+            .handle_message(Message::ChainResponse(peer.blocks.clone()))
+            .is_none());
+        assert_eq!(hashes(&app.blocks), hashes(&peer.blocks));
+    }
+
+    #[test]
+    fn new_block_from_a_peer_is_added() {
+        let mut app = App::init();
+        let block = app.next_block("gossiped".to_string());
+
+        assert!(app
+            .handle_message(Message::NewBlock(Box::new(block.clone())))
+            .is_none());
+        assert_eq!(app.blocks.last().map(|b| &b.hash), Some(&block.hash));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::app::Block;
+
+// where an `App` keeps its blocks, so the chain can live in memory, on disk or in a database
+// blocks are only ever appended after being validated, ids are sequential from the first block
+// on, which is genesis unless the chain was pruned
+pub trait ChainStore {
+    fn get_block(&self, id: u64) -> Option<&Block>;
+
+    // the block the next one has to follow, None for an empty store
+    fn tip(&self) -> Option<&Block>;
+
+    fn append(&mut self, block: Block);
+
+    // removes the tip and returns it, None for an empty store, e.g. to roll it back in a reorg
+    fn pop_tip(&mut self) -> Option<Block>;
+
+    // removes the `n` oldest blocks, or all of them if there are fewer, and returns them oldest
+    // first, e.g. for `App::prune`
+    fn remove_oldest(&mut self, n: usize) -> Vec<Block>;
+
+    // number of blocks held, genesis included
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // id of the oldest block held, genesis unless the chain was pruned
+    fn first_id(&self) -> Option<u64> {
+        let tip = self.tip()?;
+        Some((tip.id + 1).saturating_sub(self.len() as u64))
+    }
+
+    // every block held, oldest first, by default looked up id by id
+    fn iter_blocks(&self) -> Box<dyn Iterator<Item = &Block> + '_> {
+        let (first, tip) = match (self.first_id(), self.tip()) {
+            (Some(first), Some(tip)) => (first, tip.id),
+            _ => return Box::new(std::iter::empty()),
+        };
+        Box::new((first..=tip).filter_map(move |id| self.get_block(id)))
+    }
+
+    // whether any block held has the given hash, by default looked up id by id from the tip down
+    fn contains_hash(&self, hash: &str) -> bool {
+        let (first, tip) = match (self.first_id(), self.tip()) {
+            (Some(first), Some(tip)) => (first, tip.id),
+            _ => return false,
+        };
+        (first..=tip)
+            .rev()
+            .any(|id| self.get_block(id).is_some_and(|block| block.hash == hash))
+    }
+}
+
+// the default store, the whole chain in memory with the block at position 0 first
+impl ChainStore for Vec<Block> {
+    fn get_block(&self, id: u64) -> Option<&Block> {
+        // ids usually line up with the position after the first block,
+        // only fall back to a scan when they don't
+        let first = self.first()?.id;
+        id.checked_sub(first)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .and_then(|position| self.get(position))
+            .filter(|block| block.id == id)
+            .or_else(|| self.iter().find(|block| block.id == id))
+    }
+
+    fn tip(&self) -> Option<&Block> {
+        self.last()
+    }
+
+    fn append(&mut self, block: Block) {
+        self.push(block);
+    }
+
+    fn pop_tip(&mut self) -> Option<Block> {
+        self.pop()
+    }
+
+    fn remove_oldest(&mut self, n: usize) -> Vec<Block> {
+        self.drain(..n.min(Vec::len(self))).collect()
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn first_id(&self) -> Option<u64> {
+        self.first().map(|block| block.id)
+    }
+
+    fn iter_blocks(&self) -> Box<dyn Iterator<Item = &Block> + '_> {
+        Box::new(self.iter())
+    }
+
+    fn contains_hash(&self, hash: &str) -> bool {
+        self.iter().any(|block| block.hash == hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{App, Difficulty, GENESIS_TIMESTAMP};
+    use std::collections::BTreeMap;
+
+    // keeps the blocks by id, so nothing relies on positions in a `Vec`
+    #[derive(Default)]
+    struct MapStore(BTreeMap<u64, Block>);
+
+    impl ChainStore for MapStore {
+        fn get_block(&self, id: u64) -> Option<&Block> {
+            self.0.get(&id)
+        }
+
+        fn tip(&self) -> Option<&Block> {
+            self.0.values().next_back()
+        }
+
+        fn append(&mut self, block: Block) {
+            self.0.insert(block.id, block);
+        }
+
+        fn pop_tip(&mut self) -> Option<Block> {
+            self.0.pop_last().map(|(_, block)| block)
+        }
+
+        fn remove_oldest(&mut self, n: usize) -> Vec<Block> {
+            (0..n)
+                .map_while(|_| self.0.pop_first().map(|(_, block)| block))
+                .collect()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    // mines, looks up, reorgs, prunes and round trips a chain in `store`, returns the final chain
+    // timestamps are seeded, so every store ends up with the same chain
+    fn exercise<S: ChainStore>(store: S) -> Vec<Block> {
+        let mut app = App::with_store(store, Difficulty::ZERO);
+        app.seed_timestamp = Some(GENESIS_TIMESTAMP + 100);
+        app.genesis_with("store".to_string(), GENESIS_TIMESTAMP)
+            .unwrap();
+        app.mine_blocks(vec!["one".to_string(), "two".to_string()])
+            .unwrap();
+        assert_eq!(app.len(), 3);
+        assert_eq!(app.get(1).map(|block| block.data.as_str()), Some("one"));
+        let tip = app.get_tip().unwrap();
+        assert_eq!(app.get_block_by_hash(&tip.hash), Some(tip));
+
+        let mut fork = App::with_difficulty(Difficulty::ZERO)
+            .with_blocks(app.iter().take(2).cloned().collect())
+            .unwrap();
+        fork.seed_timestamp = app.seed_timestamp;
+        fork.mine_blocks(vec!["fork 2".to_string(), "fork 3".to_string()])
+            .unwrap();
+        let report = app.reorg_to(fork.blocks.clone()).unwrap();
+        assert_eq!((report.rolled_back, report.added), (1, 2));
+        assert!(app.iter().eq(fork.blocks.iter()));
+
+        let bytes = app.to_bytes();
+        app.prune(2);
+        assert_eq!(app.len(), 2);
+        assert_eq!(app.get(1), None);
+        assert_eq!(app.find_invalid_block(app.snapshot().blocks()), None);
+
+        let loaded = App::with_difficulty(Difficulty::ZERO)
+            .with_bytes(&bytes)
+            .unwrap();
+        assert_eq!(loaded.blocks, fork.blocks);
+        loaded.blocks
+    }
+
+    #[test]
+    fn app_works_on_a_vec_and_on_another_store() {
+        assert_eq!(exercise(Vec::new()), exercise(MapStore::default()));
+    }
+}
@@ -0,0 +1,137 @@
+use k256::{
+    ecdsa::{
+        signature::{Signer, Verifier},
+        Signature, SigningKey, VerifyingKey,
+    },
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// a transfer of `amount` coins between two addresses
+// an address is the hex encoded, compressed public key of its owner,
+// so the signature can be checked against `from` without any extra lookup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    // paid by `from` on top of `amount` to whoever produces the block, higher fees are mined first
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub fee: u64,
+    // hex encoded ecdsa signature by the owner of `from`, empty until signed
+    pub signature: String,
+}
+
+impl Transaction {
+    // creates an unsigned transaction, see `sign_transaction`
+    pub fn new(from: String, to: String, amount: u64) -> Self {
+        Self {
+            from,
+            to,
+            amount,
+            fee: 0,
+            signature: String::new(),
+        }
+    }
+
+    // sets the fee, has to happen before signing since the signature covers it
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // hex encoded sha256 of the JSON encoding, the same bytes the merkle tree hashes as a leaf
+    // the signature is included, so it only stays the same once the transaction is signed
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_string(self).expect("transaction can be serialized");
+        hex::encode(Sha256::digest(json.as_bytes()))
+    }
+
+    // checks the signature against the public key encoded in `from`
+    pub fn has_valid_signature(&self) -> bool {
+        verify_message(&self.from, &self.signing_payload(), &self.signature)
+    }
+
+    // bytes covered by the signature, everything except the signature itself
+    // a zero fee is left out, so transactions signed before fees existed stay valid
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = serde_json::json!({
+            "from": self.from,
+            "to": self.to,
+            "amount": self.amount,
+        });
+        if self.fee != 0 {
+            payload["fee"] = self.fee.into();
+        }
+        payload.to_string().into_bytes()
+    }
+}
+
+fn is_zero(fee: &u64) -> bool {
+    *fee == 0
+}
+
+// address owned by the holder of the secret key belonging to `public_key`
+pub fn address(public_key: &VerifyingKey) -> String {
+    hex::encode(public_key.to_encoded_point(true).as_bytes())
+}
+
+fn address_to_public_key(address: &str) -> Option<VerifyingKey> {
+    let bytes = hex::decode(address).ok()?;
+    VerifyingKey::from_sec1_bytes(&bytes).ok()
+}
+
+// signs the transaction in place, replacing any previous signature
+pub fn sign_transaction(tx: &mut Transaction, secret_key: &SigningKey) {
+    tx.signature = sign_message(&tx.signing_payload(), secret_key);
+}
+
+// checks that `tx` was signed by the secret key belonging to `public_key`
+// and has not been changed since
+pub fn verify_transaction(tx: &Transaction, public_key: &VerifyingKey) -> bool {
+    verify_signature(public_key, &tx.signing_payload(), &tx.signature)
+}
+
+// hex encoded signature of `message`, the same scheme transactions are signed with
+pub(crate) fn sign_message(message: &[u8], secret_key: &SigningKey) -> String {
+    let signature: Signature = secret_key.sign(message);
+    hex::encode(signature.as_ref())
+}
+
+// checks a signature from `sign_message` against the public key encoded in `address`
+pub(crate) fn verify_message(address: &str, message: &[u8], signature: &str) -> bool {
+    match address_to_public_key(address) {
+        Some(public_key) => verify_signature(&public_key, message, signature),
+        None => false,
+    }
+}
+
+fn verify_signature(public_key: &VerifyingKey, message: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+    public_key.verify(message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tampered_amount_breaks_the_signature() {
+        let key = SigningKey::from_bytes(&[1; 32]).unwrap();
+        let mut tx = Transaction::new(address(&key.verifying_key()), "bob".to_string(), 10);
+        sign_transaction(&mut tx, &key);
+        assert!(verify_transaction(&tx, &key.verifying_key()));
+        assert!(tx.has_valid_signature());
+
+        tx.amount = 1000;
+        assert!(!verify_transaction(&tx, &key.verifying_key()));
+        assert!(!tx.has_valid_signature());
+    }
+}
@@ -0,0 +1,25 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::{env, fs};
+
+#[test]
+fn mining_twice_grows_the_chain_file() {
+    let chain = env::temp_dir().join(format!("rust-blockchain-cli-{}.json", std::process::id()));
+    let _ = fs::remove_file(&chain);
+
+    let mut sizes = vec![];
+    for (id, data) in ["first", "second"].into_iter().enumerate() {
+        Command::cargo_bin("rust-blockchain-example")
+            .unwrap()
+            .arg("--chain")
+            .arg(&chain)
+            .args(["mine", data])
+            .assert()
+            .success()
+            .stdout(contains(format!("id: {}", id + 1)));
+        sizes.push(fs::metadata(&chain).unwrap().len());
+    }
+    fs::remove_file(&chain).unwrap();
+
+    assert!(sizes[1] > sizes[0]);
+}